@@ -0,0 +1,21 @@
+//! Helpers shared by the benchmarks.
+
+use std::time::{Duration, Instant};
+
+/// Runs `f` five times, returning its result and the fastest time. Taking the best of a few runs
+/// means one slow run doesn't skew a comparison.
+pub fn best_of_five<T, F: FnMut() -> T>(mut f: F) -> (T, Duration) {
+    let mut best: Option<(T, Duration)> = None;
+
+    for _ in 0..5 {
+        let before = Instant::now();
+        let result = f();
+        let elapsed = before.elapsed();
+
+        if best.as_ref().is_none_or(|(_, best)| elapsed < *best) {
+            best = Some((result, elapsed));
+        }
+    }
+
+    best.unwrap()
+}
@@ -0,0 +1,174 @@
+//! Times day 22 part 2 on the puzzle input and on generated reboot sequences, against the
+//! original compressed-grid solver and the signed-cuboid reactor that came between them.
+//! Run with `cargo bench --bench day22`.
+
+use std::collections::HashMap;
+use std::fs;
+use advent2021::day22;
+
+mod common;
+
+/// Generates `steps` reboot steps with cuboids spread over a similar range to the puzzle input.
+fn generate(steps: usize, seed: u64) -> String {
+    // xorshift64, so the steps are the same on every run.
+    let mut state = seed;
+    let mut next = |range: i64| {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        (state % range as u64) as i64
+    };
+
+    let mut output = String::new();
+    for _ in 0..steps {
+        let on = if next(10) < 6 { "on" } else { "off" };
+        let mut axis = || {
+            let start = next(200_000) - 100_000;
+            (start, start + 1_000 + next(30_000))
+        };
+        let (x, y, z) = (axis(), axis(), axis());
+
+        output.push_str(&format!("{} x={}..{},y={}..{},z={}..{}\n",
+                                 on, x.0, x.1, y.0, y.1, z.0, z.1));
+    }
+
+    output
+}
+
+/// The part 2 solver from before the reactor, which goes through the steps backwards and for each
+/// "on" cuboid works out how much of it later steps cover by marking a grid over the compressed
+/// coordinates of the overlaps. Only what part 2 needs is kept.
+mod compressed_grid {
+    use std::collections::HashSet;
+    use advent2021::day22::{self, Cube};
+
+    /// Gives the volume covered by the combination of the given cubes.
+    fn total_volume(cubes: &[Cube]) -> u128 {
+        let axis_map = |axis: usize| {
+            let mut map: Vec<i64> = cubes.iter()
+                .flat_map(|cube| {
+                    let (start, end) = cube.ranges()[axis];
+                    vec![start, end + 1]
+                })
+                .collect::<HashSet<i64>>().drain().collect();
+            map.sort_unstable();
+            map
+        };
+        let (x_map, y_map, z_map) = (axis_map(0), axis_map(1), axis_map(2));
+
+        let mut covered = vec![vec![vec![false; z_map.len()]; y_map.len()]; x_map.len()];
+
+        for cube in cubes.iter() {
+            for (x_index, x) in x_map.iter().enumerate() {
+                for (y_index, y) in y_map.iter().enumerate() {
+                    for (z_index, z) in z_map.iter().enumerate() {
+                        if cube.contains(&[*x, *y, *z]) {
+                            covered[x_index][y_index][z_index] = true;
+                        }
+                    }
+                }
+            }
+        }
+
+        let mut total_area = 0;
+
+        for (x_index, (x1, x2)) in x_map.iter().zip(x_map.iter().skip(1)).enumerate() {
+            for (y_index, (y1, y2)) in y_map.iter().zip(y_map.iter().skip(1)).enumerate() {
+                for (z_index, (z1, z2)) in z_map.iter().zip(z_map.iter().skip(1)).enumerate() {
+                    if covered[x_index][y_index][z_index] {
+                        total_area += ((x2 - x1) * (y2 - y1) * (z2 - z1)) as u128;
+                    }
+                }
+            }
+        }
+
+        total_area
+    }
+
+    pub fn part2(input: &str) -> u128 {
+        let mut cubes: Vec<Cube> = Vec::new();
+        let mut volume_on = 0;
+
+        for step in day22::parse::<3>(input).unwrap().into_iter().rev() {
+            let new_cube = step.cube;
+
+            if cubes.iter().any(|cube| cube.contains_cuboid(&new_cube)) {
+                // The cube is entirely contained with a previous cube, so we can just ignore it.
+                continue;
+            }
+
+            if step.on {
+                let mut overlaps = Vec::new();
+
+                for cube in cubes.iter() {
+                    if let Some(overlap) = Cube::intersection(cube, &new_cube) {
+                        overlaps.push(overlap);
+                    }
+                }
+
+                volume_on += new_cube.volume() - total_volume(&overlaps);
+            }
+
+            cubes.push(new_cube);
+        }
+
+        volume_on
+    }
+}
+
+/// The reactor from before the lit region was split into disjoint pieces: a signed sum of
+/// cuboids, where each step cancels out the overlap with every existing cuboid.
+fn signed_cuboids_part2(input: &str) -> u128 {
+    let mut cuboids: HashMap<day22::Cube, i64> = HashMap::new();
+
+    for step in day22::parse::<3>(input).unwrap() {
+        let mut changes: HashMap<day22::Cube, i64> = HashMap::new();
+
+        for (cube, sign) in cuboids.iter() {
+            if let Some(overlap) = day22::Cube::intersection(cube, &step.cube) {
+                *changes.entry(overlap).or_insert(0) -= sign;
+            }
+        }
+
+        if step.on {
+            *changes.entry(step.cube).or_insert(0) += 1;
+        }
+
+        for (cube, sign) in changes {
+            let total = cuboids.entry(cube).or_insert(0);
+            *total += sign;
+
+            if *total == 0 {
+                cuboids.remove(&cube);
+            }
+        }
+    }
+
+    let total: i128 = cuboids.iter()
+        .map(|(cube, sign)| *sign as i128 * cube.volume() as i128)
+        .sum();
+    total as u128
+}
+
+fn time(name: &str, input: &str) {
+    let (result, disjoint) = common::best_of_five(|| day22::part2(input));
+    let (reference, signed) = common::best_of_five(|| signed_cuboids_part2(input));
+    assert_eq!(result, reference, "{}: the two reactors disagree", name);
+    let (baseline, grid) = common::best_of_five(|| compressed_grid::part2(input));
+    assert_eq!(result, baseline, "{}: the reactor disagrees with the grid solver", name);
+
+    println!("{:<20} {:>24} {:>14.3?} {:>14.3?} {:>14.3?}", name, result, grid, signed, disjoint);
+}
+
+fn main() {
+    println!("{:<20} {:>24} {:>14} {:>14} {:>14}", "", "cubes on", "grid", "signed", "disjoint");
+
+    match fs::read_to_string("input/input-43.txt") {
+        Ok(input) => time("puzzle input", &input),
+        Err(err) => println!("Skipping puzzle input: {}", err),
+    }
+
+    for steps in [1000, 2000, 4000, 8000] {
+        time(&format!("{} random steps", steps), &generate(steps, 2021));
+    }
+}
@@ -0,0 +1,237 @@
+//! Times day 23 on the puzzle input and the example from the puzzle description, against the
+//! search as it was before the state was packed into an array. Both sides use Dijkstra's
+//! algorithm, so only the state representation differs.
+//! Run with `cargo bench --bench day23`.
+
+use std::fs;
+use advent2021::day23;
+
+mod common;
+
+const EXAMPLE: &str = "\
+#############
+#...........#
+###B#C#B#D###
+  #A#D#C#A#
+  #########";
+
+/// The search from before the state was packed into an array, where each state was a map from
+/// positions to amphipods. Only what the search needs is kept, and its distance estimate is left
+/// out since the packed search has a different one.
+mod hashmap_state {
+    use std::cmp::Ordering;
+    use std::collections::{BinaryHeap, HashMap};
+    use std::hash::{Hash, Hasher};
+
+    #[derive(Debug, PartialEq, Eq, Copy, Clone, Hash)]
+    struct Type(u8);
+
+    impl Type {
+        fn room_x(&self) -> u8 {
+            2 + 2 * self.0
+        }
+
+        fn cost(&self) -> u32 {
+            10_u32.pow(self.0 as u32)
+        }
+    }
+
+    #[derive(Debug, PartialEq, Eq, Hash, Copy, Clone)]
+    struct Pos {
+        x: u8,
+        y: u8,
+    }
+
+    #[derive(Clone, PartialEq, Eq)]
+    struct State {
+        positions: HashMap<Pos, Type>,
+    }
+
+    impl Hash for State {
+        fn hash<H: Hasher>(&self, state: &mut H) {
+            self.positions.iter().for_each(|(pos, t)| {
+                pos.hash(state);
+                t.hash(state);
+            })
+        }
+    }
+
+    impl State {
+        fn new_state(&self, old: &Pos, new: &Pos) -> State {
+            let mut positions = self.positions.clone();
+            let t = positions.remove(old).unwrap();
+            positions.insert(*new, t);
+            State { positions }
+        }
+
+        fn at_destination(&self, pos: &Pos, room_size: u8) -> bool {
+            if pos.y == 0 { return false; }
+
+            match self.positions.get(pos) {
+                Some(pod) if pos.x == pod.room_x() => (pos.y..=room_size)
+                    .all(|y| self.positions.get(&Pos { x: pos.x, y }) == Some(pod)),
+                _ => false,
+            }
+        }
+
+        fn is_finished(&self) -> bool {
+            self.positions.iter().all(|(pos, t)| pos.x == t.room_x())
+        }
+
+        fn room_open(&self, t: &Type, room_size: u8) -> Option<u8> {
+            for y in (1..=room_size).rev() {
+                match self.positions.get(&Pos { x: t.room_x(), y }) {
+                    Some(pod) if pod != t => return None,
+                    Some(_) => {}
+                    None => return Some(y),
+                }
+            }
+            None
+        }
+
+        fn can_leave(&self, pos: &Pos) -> bool {
+            (0..pos.y).all(|y| !self.positions.contains_key(&Pos { x: pos.x, y }))
+        }
+
+        fn clear_path(&self, x1: u8, x2: u8) -> Option<u8> {
+            let (x_min, x_max) = (x1.min(x2), x1.max(x2));
+            for x in (x_min + 1)..x_max {
+                if self.positions.contains_key(&Pos { x, y: 0 }) {
+                    return None;
+                }
+            }
+            Some(x_max - x_min)
+        }
+
+        fn possible_moves(&self, pos: &Pos, room_size: u8) -> Vec<(u32, State)> {
+            let mut moves = Vec::new();
+            if self.at_destination(pos, room_size) { return moves; }
+
+            let pod = self.positions.get(pos).unwrap();
+            if pos.y == 0 {
+                if let Some(y) = self.room_open(pod, room_size) {
+                    if let Some(x_dist) = self.clear_path(pos.x, pod.room_x()) {
+                        let dest = Pos { x: pod.room_x(), y };
+                        moves.push(((y + x_dist) as u32 * pod.cost(), self.new_state(pos, &dest)));
+                    }
+                }
+            } else if self.can_leave(pos) {
+                for x in [0, 1, 3, 5, 7, 9, 10] {
+                    if self.positions.contains_key(&Pos { x, y: 0 }) { continue; }
+
+                    if let Some(x_dist) = self.clear_path(pos.x, x) {
+                        let dest = Pos { x, y: 0 };
+                        let cost = (pos.y + x_dist) as u32 * pod.cost();
+                        moves.push((cost, self.new_state(pos, &dest)));
+                    }
+                }
+            }
+            moves
+        }
+    }
+
+    #[derive(Clone, PartialEq, Eq)]
+    struct SearchState {
+        cost: u32,
+        state: State,
+    }
+
+    impl PartialOrd for SearchState {
+        fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+            Some(self.cmp(other))
+        }
+    }
+
+    impl Ord for SearchState {
+        fn cmp(&self, other: &Self) -> Ordering {
+            other.cost.cmp(&self.cost)
+        }
+    }
+
+    fn search(start: &State, room_size: u8) -> Option<u32> {
+        let mut costs: HashMap<State, u32> = HashMap::new();
+        // Kept so the search does the same bookkeeping as it did for returning the moves.
+        let mut previous: HashMap<State, (State, u32)> = HashMap::new();
+        let mut heap = BinaryHeap::new();
+
+        costs.insert(start.clone(), 0);
+        heap.push(SearchState { cost: 0, state: start.clone() });
+
+        while let Some(SearchState { cost, state: current }) = heap.pop() {
+            if current.is_finished() {
+                return Some(cost);
+            }
+            if cost > *costs.get(&current).unwrap_or(&u32::MAX) { continue; }
+
+            let moves: Vec<(u32, State)> = current.positions.keys()
+                .flat_map(|pos| current.possible_moves(pos, room_size))
+                .collect();
+            for (step_cost, next) in moves {
+                let new_cost = cost + step_cost;
+                if new_cost < *costs.get(&next).unwrap_or(&u32::MAX) {
+                    costs.insert(next.clone(), new_cost);
+                    previous.insert(next.clone(), (current.clone(), step_cost));
+                    heap.push(SearchState { cost: new_cost, state: next });
+                }
+            }
+        }
+
+        None
+    }
+
+    fn solve(input: &str) -> Result<u32, String> {
+        let mut positions = HashMap::new();
+        for (y, line) in input.lines().skip(1).enumerate() {
+            for (x, c) in line.chars().skip(1).enumerate() {
+                if ('A'..='D').contains(&c) {
+                    positions.insert(Pos { x: x as u8, y: y as u8 }, Type(c as u8 - b'A'));
+                }
+            }
+        }
+
+        let room_size = positions.keys().map(|pos| pos.y).max().unwrap_or(0);
+        search(&State { positions }, room_size)
+            .ok_or_else(|| String::from("There's no way to organise the amphipods"))
+    }
+
+    pub fn part1(input: &str) -> Result<u32, String> {
+        solve(input)
+    }
+
+    pub fn part2(input: &str) -> Result<u32, String> {
+        solve(&super::day23::unfold(input))
+    }
+}
+
+type Part = fn(&str) -> Result<u32, String>;
+
+fn part1(input: &str) -> Result<u32, String> {
+    day23::solve_with(input, day23::Strategy::Dijkstra).map(|(solution, _)| solution.energy)
+}
+
+fn part2(input: &str) -> Result<u32, String> {
+    part1(&day23::unfold(input))
+}
+
+fn time(name: &str, part: Part, reference: Part, input: &str) {
+    let (result, packed) = common::best_of_five(|| part(input));
+    let (reference_result, before) = common::best_of_five(|| reference(input));
+    assert_eq!(result, reference_result, "{}: the two searches disagree", name);
+
+    println!("{:<24} {:>16} {:>12.3?} {:>12.3?}", name, format!("{:?}", result), before, packed);
+}
+
+fn main() {
+    println!("{:<24} {:>16} {:>12} {:>12}", "", "energy", "hashmap", "packed");
+
+    time("example part 1", part1, hashmap_state::part1, EXAMPLE);
+    time("example part 2", part2, hashmap_state::part2, EXAMPLE);
+
+    match fs::read_to_string("input/input-45.txt") {
+        Ok(input) => {
+            time("puzzle input part 1", part1, hashmap_state::part1, &input);
+            time("puzzle input part 2", part2, hashmap_state::part2, &input);
+        }
+        Err(err) => println!("Skipping puzzle input: {}", err),
+    }
+}
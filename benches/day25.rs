@@ -0,0 +1,32 @@
+//! Times day 25 on the puzzle input and the example from the puzzle description.
+//! Run with `cargo bench --bench day25`.
+
+use std::fs;
+use advent2021::day25;
+
+mod common;
+
+const EXAMPLE: &str = "\
+v...>>.vv>
+.vv>>.vv..
+>>.>v>...v
+>>v>>.>.v.
+v>v.vv.v..
+>.>>..v...
+.vv..>.>v.
+v.v..>>v.v
+....v..v.>";
+
+fn time(name: &str, input: &str) {
+    let (result, best) = common::best_of_five(|| day25::part1(input));
+    println!("{:<24} {:>16} {:>12.3?}", name, format!("{:?}", result), best);
+}
+
+fn main() {
+    time("example part 1", EXAMPLE);
+
+    match fs::read_to_string("input/input-49.txt") {
+        Ok(input) => time("puzzle input part 1", &input),
+        Err(err) => println!("Skipping puzzle input: {}", err),
+    }
+}
@@ -1,4 +1,7 @@
 use std::collections::HashSet;
+use std::io;
+use std::path::Path;
+use crate::netpbm;
 
 #[derive(Debug, Copy, Clone, PartialEq, Hash, Eq, PartialOrd, Ord)]
 struct Coord {
@@ -127,6 +130,23 @@ impl Map {
     }
 }
 
+/// Writes the energy levels before and after each step to `dir` as PGM files, so flashing octopuses
+/// (at 0) show up as black.
+pub fn dump_steps(input: &str, steps: usize, dir: &Path) -> io::Result<()> {
+    let mut map = Map::parse(input);
+
+    for step in 0..=steps {
+        if step > 0 { map.step(); }
+
+        let pgm = netpbm::to_pgm(map.width, map.height, 9, |x, y| {
+            map.get(&Coord::new(x, y)) as u16
+        });
+        netpbm::write_to_dir(dir, &format!("step-{:03}.pgm", step), &pgm)?;
+    }
+
+    Ok(())
+}
+
 pub fn part1(input: &str) -> i32 {
     let mut map = Map::parse(input);
     let mut total_flashes = 0;
@@ -1,4 +1,7 @@
 use std::collections::HashSet;
+use std::io;
+use std::path::Path;
+use crate::netpbm;
 
 #[derive(Debug, Copy, Clone, PartialEq, Hash, Eq, PartialOrd, Ord)]
 struct Coord {
@@ -45,10 +48,10 @@ fn parse(input: &str) -> (HashSet<Coord>, Vec<Fold>) {
         if line.is_empty() { continue; }
 
         if line.starts_with("fold along y=") {
-            let y = line.split('=').skip(1).next().unwrap().parse().unwrap();
+            let y = line.split('=').nth(1).unwrap().parse().unwrap();
             folds.push(Fold::AlongY(y))
         } else if line.starts_with("fold along x=") {
-            let x = line.split('=').skip(1).next().unwrap().parse().unwrap();
+            let x = line.split('=').nth(1).unwrap().parse().unwrap();
             folds.push(Fold::AlongX(x))
         } else {
             let mut parts = line.split(',');
@@ -65,7 +68,7 @@ pub fn part1(input: &str) -> usize {
     let (mut points, folds) = parse(input);
 
     let first_fold = folds.first().unwrap();
-    points = points.iter().map(|point| point.flipped(&first_fold)).collect();
+    points = points.iter().map(|point| point.flipped(first_fold)).collect();
     points.len()
 }
 
@@ -95,6 +98,34 @@ pub fn part2(input: &str) -> String {
     output
 }
 
+fn to_pbm(points: &HashSet<Coord>, width: usize, height: usize) -> String {
+    netpbm::to_pbm(width, height, |x, y| points.contains(&Coord { x, y }))
+}
+
+/// Writes the paper before and after each fold to `dir` as PBM files.
+pub fn dump_folds(input: &str, dir: &Path) -> io::Result<()> {
+    let (mut points, folds) = parse(input);
+
+    let mut width = points.iter().map(|point| point.x).max().unwrap_or(0) + 1;
+    let mut height = points.iter().map(|point| point.y).max().unwrap_or(0) + 1;
+    netpbm::write_to_dir(dir, "fold-00.pbm", &to_pbm(&points, width, height))?;
+
+    for (i, fold) in folds.iter().enumerate() {
+        points = points.iter().map(|point| point.flipped(fold)).collect();
+
+        // The fold line itself disappears, so the paper shrinks to just the part before it.
+        match fold {
+            Fold::AlongX(x) => width = *x,
+            Fold::AlongY(y) => height = *y,
+        }
+
+        let name = format!("fold-{:02}.pbm", i + 1);
+        netpbm::write_to_dir(dir, &name, &to_pbm(&points, width, height))?;
+    }
+
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -126,4 +157,18 @@ fold along x=5";
 
         assert_eq!(17, part1(input));
     }
+
+    #[test]
+    fn dump() {
+        let name = format!("advent2021-day13-dump-{}", std::process::id());
+        let dir = std::env::temp_dir().join(name);
+        dump_folds("0,0\n4,0\n3,2\n\nfold along y=1\nfold along x=2", &dir).unwrap();
+
+        let read = |name| std::fs::read_to_string(dir.join(name)).unwrap();
+        assert_eq!("P1\n5 3\n10001\n00000\n00010\n", read("fold-00.pbm"));
+        assert_eq!("P1\n5 1\n10011\n", read("fold-01.pbm"));
+        assert_eq!("P1\n2 1\n11\n", read("fold-02.pbm"));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
 }
\ No newline at end of file
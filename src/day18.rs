@@ -1,6 +1,7 @@
 use crate::day18::Token::{CloseBracket, OpenBracket, Number};
 use std::fmt;
 use std::fmt::{Formatter, Write};
+use std::thread;
 
 type Num = u32;
 
@@ -47,7 +48,7 @@ fn parse(input: &str) -> SnailNumber {
             '[' => Some(OpenBracket),
             ']' => Some(CloseBracket),
             ',' => None,
-            other => other.to_digit(10).map(|d| Number(d))
+            other => other.to_digit(10).map(Number)
         }
     }).collect()
 }
@@ -61,27 +62,33 @@ fn add_to(a: &mut SnailNumber, b: SnailNumber) {
     a.push(Token::CloseBracket);
 }
 
-fn add_to_previous_number(n: &mut SnailNumber, index: usize, num: Num) {
+/// Returns whether there was a number to add to (if not, the value is dropped).
+fn add_to_previous_number(n: &mut SnailNumber, index: usize, num: Num) -> bool {
     n.iter_mut()
         .take(index - 1)
         .filter(|token| matches!(token, Number(_)))
         .last()
-        .map(|token| {
-           *token = token.add(num);
-        });
+        .map(|token| *token = token.add(num))
+        .is_some()
 }
 
-fn add_to_next_number(n: &mut SnailNumber, index: usize, num: Num) {
+fn add_to_next_number(n: &mut SnailNumber, index: usize, num: Num) -> bool {
     n.iter_mut()
         .skip(index + 1)
-        .filter(|token| matches!(token, Number(_)))
-        .next()
-        .map(|token| {
-            *token = token.add(num);
-        });
+        .find(|token| matches!(token, Number(_)))
+        .map(|token| *token = token.add(num))
+        .is_some()
+}
+
+struct Explosion {
+    left: Num,
+    right: Num,
+    depth: usize,
+    carried_left: bool,
+    carried_right: bool,
 }
 
-fn explode(n: &mut SnailNumber) -> bool {
+fn explode(n: &mut SnailNumber) -> Option<Explosion> {
     let mut depth = 0;
     let mut i = 0;
 
@@ -93,13 +100,13 @@ fn explode(n: &mut SnailNumber) -> bool {
                 let left = n.get(i+1).unwrap().get_num();
                 let right = n.get(i+2).unwrap().get_num();
 
-                add_to_previous_number(n, i + 1, left);
-                add_to_next_number(n, i + 2, right);
+                let carried_left = add_to_previous_number(n, i + 1, left);
+                let carried_right = add_to_next_number(n, i + 2, right);
 
                 n.drain(i..(i+4));
                 n.insert(i, Number(0));
 
-                return true;
+                return Some(Explosion { left, right, depth, carried_left, carried_right });
             }
         }
 
@@ -112,14 +119,15 @@ fn explode(n: &mut SnailNumber) -> bool {
         i += 1
     }
 
-    return false;
+    None
 }
 
-fn split(n: &mut SnailNumber) -> bool {
+/// Returns the value that was split, if any.
+fn split(n: &mut SnailNumber) -> Option<Num> {
     let mut i = 0;
 
     while i < n.len() {
-        let current = n.get(i).unwrap().clone();
+        let current = *n.get(i).unwrap();
 
         match current {
             OpenBracket => {}
@@ -132,7 +140,7 @@ fn split(n: &mut SnailNumber) -> bool {
                     n.insert(i + 2, Number(value / 2 + value % 2));
                     n.insert(i + 3, CloseBracket);
 
-                    return true;
+                    return Some(value);
                 }
             }
         }
@@ -140,20 +148,84 @@ fn split(n: &mut SnailNumber) -> bool {
         i += 1;
     }
 
-    return false;
+    None
 }
 
 fn reduce(n: &mut SnailNumber) {
-    let mut changed = true;
-    while changed {
-        changed = explode(n);
+    while explode(n).is_some() || split(n).is_some() {}
+}
 
-        if !changed {
-            changed = split(n);
+/// A single step of an addition, as listed in the puzzle description.
+#[derive(Debug, PartialEq, Eq)]
+pub enum ReduceStep {
+    Addition { result: String },
+    /// The pair `[left,right]` nested `depth` pairs deep exploded. The carried values are `None`
+    /// if there was no regular number on that side to receive them.
+    Explode {
+        left: Num,
+        right: Num,
+        depth: usize,
+        carried_left: Option<Num>,
+        carried_right: Option<Num>,
+        result: String,
+    },
+    Split { value: Num, result: String },
+}
+
+impl fmt::Display for ReduceStep {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let carried = |value: &Option<Num>| match value {
+            Some(value) => format!("{}", value),
+            None => "nothing".to_string(),
+        };
+
+        match self {
+            ReduceStep::Addition { result } => write!(f, "after addition: {}", result),
+            ReduceStep::Explode { left, right, depth, carried_left, carried_right, result } => {
+                write!(f, "after explode:  {}  ([{},{}] at depth {}, carried {} left and {} right)",
+                       result, left, right, depth, carried(carried_left), carried(carried_right))
+            }
+            ReduceStep::Split { value, result } => {
+                write!(f, "after split:    {}  ({} split)", result, value)
+            }
         }
     }
 }
 
+/// Reduces `n` like `reduce`, recording each explode and split. Snail numbers aren't public, so
+/// `trace_addition` is the way to get a trace from outside this module.
+fn reduce_with_trace(n: &mut SnailNumber) -> Vec<ReduceStep> {
+    let mut steps = Vec::new();
+
+    loop {
+        if let Some(explosion) = explode(n) {
+            steps.push(ReduceStep::Explode {
+                left: explosion.left,
+                right: explosion.right,
+                depth: explosion.depth,
+                carried_left: Some(explosion.left).filter(|_| explosion.carried_left),
+                carried_right: Some(explosion.right).filter(|_| explosion.carried_right),
+                result: format_number(n),
+            });
+        } else if let Some(value) = split(n) {
+            steps.push(ReduceStep::Split { value, result: format_number(n) });
+        } else {
+            return steps;
+        }
+    }
+}
+
+/// Adds `a` and `b`, returning every step taken: the addition itself, then each step of
+/// `reduce_with_trace`. The last step holds the reduced sum.
+pub fn trace_addition(a: &str, b: &str) -> Vec<ReduceStep> {
+    let mut n = parse(a);
+    add_to(&mut n, parse(b));
+
+    let mut steps = vec![ReduceStep::Addition { result: format_number(&n) }];
+    steps.append(&mut reduce_with_trace(&mut n));
+    steps
+}
+
 fn add_and_reduce(a: &mut SnailNumber, b: SnailNumber) {
     add_to(a, b);
     reduce(a);
@@ -169,28 +241,112 @@ fn sum(numbers: &[SnailNumber]) -> SnailNumber {
 }
 
 pub fn part1(input: &str) -> Num {
-    let v : Vec<SnailNumber> = input.lines().map(|line| parse(line)).collect();
+    let v : Vec<SnailNumber> = input.lines().map(parse).collect();
     magnitude(&mut sum(&v).iter())
 }
 
 pub fn part2(input: &str) -> Num {
-    let v : Vec<SnailNumber> = input.lines().map(|line| parse(line)).collect();
+    best_pair(input).map(|pair| pair.magnitude).unwrap_or(0)
+}
+
+/// The ordered pair of input lines whose sum has the largest magnitude.
+#[derive(Debug, PartialEq, Eq)]
+pub struct BestPair {
+    /// Index (into the input lines) of the left operand.
+    pub left: usize,
+    /// Index (into the input lines) of the right operand.
+    pub right: usize,
+    /// The reduced sum, written in the same format as the input.
+    pub result: String,
+    pub magnitude: Num,
+}
+
+/// Finds the best ordered pair, splitting the search across all available threads. Ties are
+/// broken in favour of the lowest `(left, right)`, so the result doesn't depend on scheduling.
+pub fn best_pair(input: &str) -> Option<BestPair> {
+    let threads = thread::available_parallelism().map(|n| n.get()).unwrap_or(1);
+    best_pair_with_threads(input, threads)
+}
+
+pub fn best_pair_with_threads(input: &str, threads: usize) -> Option<BestPair> {
+    let v : Vec<SnailNumber> = input.lines().map(parse).collect();
+    let threads = threads.clamp(1, v.len().max(1));
+
+    let best = thread::scope(|scope| {
+        let handles: Vec<_> = (0..threads)
+            .map(|t| {
+                let v = &v;
+                // Interleave the rows so each thread gets a similar amount of work.
+                scope.spawn(move || best_in_rows(v, (t..v.len()).step_by(threads)))
+            })
+            .collect();
+
+        handles.into_iter()
+            .filter_map(|handle| handle.join().unwrap())
+            .reduce(better)
+    });
+
+    best.map(|(magnitude, left, right)| {
+        let mut buffer = SnailNumber::new();
+        add_into(&mut buffer, &v[left], &v[right]);
+
+        BestPair { left, right, result: format_number(&buffer), magnitude }
+    })
+}
 
-    let mut best_magnitude = 0 as Num;
-    for i in 0..v.len() {
+/// Returns `(magnitude, left, right)` of the best pair whose left operand is in `rows`, reusing a
+/// single buffer for every addition.
+fn best_in_rows<I: Iterator<Item = usize>>(v: &[SnailNumber], rows: I)
+    -> Option<(Num, usize, usize)> {
+    let mut buffer = SnailNumber::new();
+    let mut best = None;
+
+    for i in rows {
         for j in 0..v.len() {
             if i == j { continue; }
 
-            let m = magnitude(&mut sum(
-                &[
-                    v.get(i).unwrap().clone(),
-                    v.get(j).unwrap().clone()
-                ][..]).iter());
+            add_into(&mut buffer, &v[i], &v[j]);
+            let candidate = (magnitude(&mut buffer.iter()), i, j);
+
+            best = Some(match best {
+                Some(current) => better(current, candidate),
+                None => candidate,
+            });
+        }
+    }
+
+    best
+}
+
+fn better(a: (Num, usize, usize), b: (Num, usize, usize)) -> (Num, usize, usize) {
+    if b.0 > a.0 || (b.0 == a.0 && (b.1, b.2) < (a.1, a.2)) { b } else { a }
+}
+
+/// Writes `[a + b]` into `buffer` (overwriting what was there) and reduces it in place.
+fn add_into(buffer: &mut SnailNumber, a: &[Token], b: &[Token]) {
+    buffer.clear();
+    buffer.push(OpenBracket);
+    buffer.extend_from_slice(a);
+    buffer.extend_from_slice(b);
+    buffer.push(CloseBracket);
+    reduce(buffer);
+}
+
+fn format_number(n: &[Token]) -> String {
+    let mut output = String::new();
 
-            best_magnitude = Num::max(m, best_magnitude)
+    for (i, token) in n.iter().enumerate() {
+        // A comma goes between two siblings, ie whenever something follows a number or a pair.
+        let previous = if i == 0 { None } else { n.get(i - 1) };
+        if matches!(previous, Some(Number(_)) | Some(CloseBracket))
+            && !matches!(token, CloseBracket) {
+            output.push(',');
         }
+
+        output.push_str(&format!("{:?}", token));
     }
-    best_magnitude
+
+    output
 }
 
 fn magnitude<'a, I: Iterator<Item = &'a Token>>(stream: &mut I) -> Num {
@@ -246,6 +402,24 @@ mod test {
         assert_eq!(parse("[[[[0,7],4],[[7,8],[6,0]]],[8,1]]"), num);
     }
 
+    #[test]
+    fn test_trace_addition() {
+        let steps = trace_addition("[[[[4,3],4],4],[7,[[8,4],9]]]", "[1,1]");
+        let listing: Vec<String> = steps.iter().map(|step| step.to_string()).collect();
+
+        assert_eq!(vec![
+            "after addition: [[[[[4,3],4],4],[7,[[8,4],9]]],[1,1]]",
+            "after explode:  [[[[0,7],4],[7,[[8,4],9]]],[1,1]]  \
+                ([4,3] at depth 4, carried nothing left and 3 right)",
+            "after explode:  [[[[0,7],4],[15,[0,13]]],[1,1]]  \
+                ([8,4] at depth 4, carried 8 left and 4 right)",
+            "after split:    [[[[0,7],4],[[7,8],[0,13]]],[1,1]]  (15 split)",
+            "after split:    [[[[0,7],4],[[7,8],[0,[6,7]]]],[1,1]]  (13 split)",
+            "after explode:  [[[[0,7],4],[[7,8],[6,0]]],[8,1]]  \
+                ([6,7] at depth 4, carried 6 left and 7 right)",
+        ], listing);
+    }
+
     #[test]
     fn test_magnitude() {
         assert_eq!(29, magnitude(&mut parse("[9,1]").iter()));
@@ -308,4 +482,37 @@ mod test {
 
         assert_eq!(3488, part1(input));
     }
+
+    const PART2_EXAMPLE: &str = "\
+[[[0,[5,8]],[[1,7],[9,6]]],[[4,[1,2]],[[1,4],2]]]
+[[[5,[2,8]],4],[5,[[9,9],0]]]
+[6,[[[6,2],[5,6]],[[7,6],[4,7]]]]
+[[[6,[0,7]],[0,9]],[4,[9,[9,0]]]]
+[[[7,[6,4]],[3,[1,3]]],[[[5,5],1],9]]
+[[6,[[7,3],[3,2]]],[[[3,8],[5,7]],4]]
+[[[[5,4],[7,7]],8],[[8,3],8]]
+[[9,3],[[9,9],[6,[4,9]]]]
+[[2,[[7,7],7]],[[5,8],[[9,3],[0,2]]]]
+[[[[5,2],5],[8,[3,7]]],[[5,[7,5]],[4,4]]]";
+
+    #[test]
+    fn given_example_part2() {
+        assert_eq!(3993, part2(PART2_EXAMPLE));
+
+        let expected = BestPair {
+            left: 8,
+            right: 0,
+            result: "[[[[7,8],[6,6]],[[6,0],[7,7]]],[[[7,8],[8,8]],[[7,9],[0,6]]]]".to_string(),
+            magnitude: 3993,
+        };
+        for threads in 1..=4 {
+            assert_eq!(Some(&expected), best_pair_with_threads(PART2_EXAMPLE, threads).as_ref());
+        }
+    }
+
+    #[test]
+    fn test_format_number() {
+        let n = "[[[[1,1],[2,2]],[3,3]],[4,4]]";
+        assert_eq!(n, format_number(&parse(n)));
+    }
 }
\ No newline at end of file
@@ -1,132 +1,363 @@
 use std::fmt::{Debug, Formatter, Write};
+use std::io;
+use std::path::Path;
 use std::str::FromStr;
-use indicatif::ProgressBar;
-
-struct Image {
-    contents: Vec<Vec<char>>,
-    bg: char,
+use crate::netpbm;
+
+const BITS: usize = 64;
+
+/// A finite image on an infinite background. Each row is packed into `u64`s, with pixel `x` at bit
+/// `x % 64` of word `x / 64`. Bits past `width` are always zero.
+#[derive(PartialEq, Eq)]
+pub struct Image {
+    rows: Vec<Vec<u64>>,
+    width: usize,
+    height: usize,
+    bg: bool,
 }
 
 impl Image {
-    fn get(&self, x: i32, y: i32) -> bool {
-        if x < 0 || y < 0 { return '#' == self.bg; }
+    fn new(width: usize, height: usize, bg: bool) -> Image {
+        Image {
+            rows: vec![vec![0; width.div_ceil(BITS)]; height],
+            width,
+            height,
+            bg,
+        }
+    }
 
-        if let Some(row) = self.contents.get(y as usize) {
-            if let Some(c) = row.get(x as usize) {
-                return '#' == *c;
-            }
+    pub fn get(&self, x: i32, y: i32) -> bool {
+        if x < 0 || y < 0 || x as usize >= self.width || y as usize >= self.height {
+            return self.bg;
         }
-        '#' == self.bg
+
+        let (x, y) = (x as usize, y as usize);
+        (self.rows[y][x / BITS] >> (x % BITS)) & 1 == 1
+    }
+
+    fn set(&mut self, x: usize, y: usize) {
+        self.rows[y][x / BITS] |= 1 << (x % BITS);
     }
 
+    /// The reference (slow) version of the index computed by the sliding window in `process_image`.
+    #[cfg(test)]
     fn get_num(&self, x: i32, y: i32) -> usize {
         let mut output: usize = 0;
-        let x: i32 = x as i32;
-        let y: i32 = y as i32;
-
-        output |= self.get(x - 1, y - 1) as usize; output <<= 1;
-        output |= self.get(x    , y - 1) as usize; output <<= 1;
-        output |= self.get(x + 1, y - 1) as usize; output <<= 1;
-        output |= self.get(x - 1, y    ) as usize; output <<= 1;
-        output |= self.get(x    , y    ) as usize; output <<= 1;
-        output |= self.get(x + 1, y    ) as usize; output <<= 1;
-        output |= self.get(x - 1, y + 1) as usize; output <<= 1;
-        output |= self.get(x    , y + 1) as usize; output <<= 1;
-        output |= self.get(x + 1, y + 1) as usize;
+
+        for dy in -1..=1 {
+            for dx in -1..=1 {
+                output = (output << 1) | self.get(x + dx, y + dy) as usize;
+            }
+        }
 
         output
     }
 
-    fn width(&self) -> usize {
-        self.contents.first().map(|row| row.len()).unwrap_or(0)
+    /// Lit pixels are written as black. The infinite background can't be drawn, so whether it's
+    /// lit is noted in a comment after the magic number instead.
+    fn to_pbm(&self) -> String {
+        let pbm = netpbm::to_pbm(self.width, self.height, |x, y| self.get(x as i32, y as i32));
+        let background = if self.bg { "lit" } else { "dark" };
+        pbm.replacen('\n', &format!("\n# background: {}\n", background), 1)
+    }
+
+    /// Reads an image on a dark background. Comments, including the one `to_pbm` writes about the
+    /// background, are ignored.
+    fn from_pbm(input: &str) -> Result<Image, String> {
+        let bitmap = netpbm::from_pbm(input)?;
+        let mut image = Image::new(bitmap.width, bitmap.height, false);
+
+        for y in 0..bitmap.height {
+            for x in 0..bitmap.width {
+                if bitmap.get(x, y) { image.set(x, y); }
+            }
+        }
+
+        Ok(image)
     }
 
-    fn height(&self) -> usize {
-        self.contents.len()
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
     }
 }
 
 impl FromStr for Image {
-    type Err = ();
+    type Err = String;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        Ok(Image {
-            contents: s.lines().map(|line| line.chars().collect()).collect(),
-            bg: '.'
-        })
+        let lines: Vec<&str> = s.lines().collect();
+        let width = lines.first().map(|line| line.len()).unwrap_or(0);
+        let mut image = Image::new(width, lines.len(), false);
+
+        for (y, line) in lines.iter().enumerate() {
+            if line.len() != width {
+                return Err(format!("Row {} has length {}, expected {}", y, line.len(), width));
+            }
+
+            for (x, c) in line.chars().enumerate() {
+                match c {
+                    '#' => image.set(x, y),
+                    '.' => {}
+                    other => return Err(format!("Unexpected pixel '{}' in row {}", other, y)),
+                }
+            }
+        }
+
+        Ok(image)
     }
 }
 
 impl Debug for Image {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        self.contents.iter()
-            .try_for_each(|row| {
-                f.write_str(row.iter().collect::<String>().as_str())?;
-                f.write_char('\n')
-            })
+        (0..self.height as i32).try_for_each(|y| {
+            (0..self.width as i32)
+                .try_for_each(|x| f.write_char(if self.get(x, y) { '#' } else { '.' }))?;
+            f.write_char('\n')
+        })
     }
 }
 
-fn process_image(image: &Image, algorithm: &str) -> Image {
-    let mut new_image : Vec<Vec<char>> = Vec::new();
+/// A rule deciding the next state of a pixel from the `kernel`×`kernel` square centred on it.
+///
+/// The square is passed to the rule as an index, read row by row from the top left, with the top
+/// left pixel as the most significant bit (so for a 3×3 kernel this is the puzzle's 9-bit number).
+pub enum Rule {
+    /// A lookup table with one bit per index. Only practical for kernels up to 5×5.
+    Table { kernel: usize, lookup: Vec<u64> },
+    /// A Life-like rule: a dark pixel becomes lit if its number of lit neighbours is in `birth`,
+    /// and a lit pixel stays lit if the number is in `survive`. Both are indexed by count.
+    LifeLike { kernel: usize, birth: Vec<bool>, survive: Vec<bool> },
+}
 
-    let bg = if image.bg == '.' {
-        algorithm.chars().next().unwrap()
-    } else {
-        algorithm.chars().nth(0b111111111).unwrap()
-    };
+const MAX_TABLE_KERNEL: usize = 5;
+// The index has to fit in a u64.
+const MAX_KERNEL: usize = 7;
+
+fn check_kernel(kernel: usize, max: usize) -> Result<(), String> {
+    if kernel.is_multiple_of(2) || kernel > max {
+        return Err(format!("Kernel size must be odd and at most {}, got {}", max, kernel));
+    }
+    Ok(())
+}
+
+impl Rule {
+    /// Builds a lookup table by evaluating `f` for every index.
+    pub fn from_fn<F: Fn(u64) -> bool>(kernel: usize, f: F) -> Result<Rule, String> {
+        check_kernel(kernel, MAX_TABLE_KERNEL)?;
+
+        let size = 1_usize << (kernel * kernel);
+        let mut lookup = vec![0; size.div_ceil(BITS)];
+        for index in 0..size {
+            if f(index as u64) {
+                lookup[index / BITS] |= 1 << (index % BITS);
+            }
+        }
+
+        Ok(Rule::Table { kernel, lookup })
+    }
+
+    pub fn life_like(kernel: usize, birth: &[usize], survive: &[usize]) -> Result<Rule, String> {
+        check_kernel(kernel, MAX_KERNEL)?;
+
+        let neighbours = kernel * kernel - 1;
+        let to_table = |counts: &[usize]| -> Result<Vec<bool>, String> {
+            let mut table = vec![false; neighbours + 1];
+            for &count in counts {
+                if count > neighbours {
+                    return Err(format!("A {0}×{0} kernel has at most {1} neighbours, got {2}",
+                                       kernel, neighbours, count));
+                }
+                table[count] = true;
+            }
+            Ok(table)
+        };
+
+        Ok(Rule::LifeLike { kernel, birth: to_table(birth)?, survive: to_table(survive)? })
+    }
+
+    pub fn kernel(&self) -> usize {
+        match self {
+            Rule::Table { kernel, .. } => *kernel,
+            Rule::LifeLike { kernel, .. } => *kernel,
+        }
+    }
+
+    pub fn apply(&self, index: u64) -> bool {
+        match self {
+            Rule::Table { lookup, .. } => {
+                let index = index as usize;
+                (lookup[index / BITS] >> (index % BITS)) & 1 == 1
+            }
+            Rule::LifeLike { kernel, birth, survive } => {
+                let centre = (kernel * kernel) / 2;
+                let lit = (index >> centre) & 1 == 1;
+                let neighbours = index.count_ones() as usize - lit as usize;
+
+                if lit { survive[neighbours] } else { birth[neighbours] }
+            }
+        }
+    }
+
+    fn parse_life_like(s: &str) -> Result<Rule, String> {
+        let mut birth = None;
+        let mut survive = None;
+        let mut kernel = 3;
+
+        for part in s.split('/') {
+            let (counts, target) = match part.chars().next() {
+                Some('B') | Some('b') => (&part[1..], &mut birth),
+                Some('S') | Some('s') => (&part[1..], &mut survive),
+                Some('K') | Some('k') => {
+                    kernel = part[1..].parse()
+                        .map_err(|_| format!("Invalid kernel size in \"{}\"", part))?;
+                    continue;
+                }
+                _ => return Err(format!("Expected B, S or K in rule \"{}\"", s)),
+            };
+
+            // Counts are single digits unless they're separated by commas.
+            let counts: Option<Vec<usize>> = if counts.contains(',') {
+                counts.split(',')
+                    .filter(|count| !count.is_empty())
+                    .map(|count| count.parse().ok())
+                    .collect()
+            } else {
+                counts.chars()
+                    .map(|c| c.to_digit(10).map(|d| d as usize))
+                    .collect()
+            };
+            *target = Some(counts.ok_or(format!("Invalid neighbour count in \"{}\"", part))?);
+        }
+
+        match (birth, survive) {
+            (Some(birth), Some(survive)) => Rule::life_like(kernel, &birth, &survive),
+            _ => Err(format!("Rule \"{}\" needs both a B and an S part", s)),
+        }
+    }
+}
+
+impl FromStr for Rule {
+    type Err = String;
+
+    /// Parses either a Life-like rule string such as `B3/S23`, or a table in the puzzle's format,
+    /// with one `#` or `.` per index. Life-like rules use a 3×3 kernel unless a `K` part gives
+    /// another size, and counts of 10 or more are written with commas between the counts, as in
+    /// `B3,12/S2,3/K5`. The kernel size of a table is inferred from its length, so the puzzle's
+    /// 512 characters give a 3×3 kernel.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if s.starts_with(['B', 'b', 'S', 's', 'K', 'k']) {
+            return Rule::parse_life_like(s);
+        }
 
-    for y in 0..(image.height() as i32 + 2) {
-        let y = y - 1;
+        let kernel = (1..=MAX_TABLE_KERNEL).step_by(2)
+            .find(|kernel| 1 << (kernel * kernel) == s.len())
+            .ok_or(format!("Table has length {}, which isn't 2^(k×k) for an odd k", s.len()))?;
 
-        let mut new_row : Vec<char> = Vec::new();
-        for x in 0..(image.width() as i32 + 2) {
-            let x = x - 1;
+        let lit: Vec<bool> = s.chars()
+            .map(|c| match c {
+                '#' => Ok(true),
+                '.' => Ok(false),
+                other => Err(format!("Unexpected character '{}' in algorithm", other)),
+            })
+            .collect::<Result<_, _>>()?;
+
+        Rule::from_fn(kernel, |index| lit[index as usize])
+    }
+}
 
-            new_row.push(algorithm.chars().nth(image.get_num(x, y)).unwrap());
+/// Applies `rule` to every pixel, including the background. The image grows by half a kernel on
+/// each side, since pixels that close to the edge can see into it.
+pub fn process_image(image: &Image, rule: &Rule) -> Image {
+    let kernel = rule.kernel();
+    let half = kernel / 2;
+
+    let full: u64 = (1 << (kernel * kernel)) - 1;
+    let bg = rule.apply(if image.bg { full } else { 0 });
+    let mut new_image = Image::new(image.width() + 2 * half, image.height() + 2 * half, bg);
+
+    // Each column of the window contributes one bit per row, at positions (kernel - 1 - row) *
+    // kernel + (kernel - 1 - column). Sliding the window right shifts each bit up by one, and the
+    // bits for the column that leaves would spill into the row above, so they're masked off.
+    let new_column: u64 = (0..kernel).map(|row| 1 << (row * kernel)).sum();
+    let keep = full & !new_column;
+
+    let half = half as i32;
+    for new_y in 0..new_image.height() {
+        // Pixel (new_x, new_y) of the new image is centred on (new_x - half, new_y - half) in the
+        // old one, so its window spans old columns new_x - 2 * half to new_x.
+        let top = new_y as i32 - 2 * half;
+        let column = |x: i32| {
+            (0..kernel as i32)
+                .fold(0, |index, row| index << kernel | image.get(x, top + row) as u64)
+        };
+
+        let mut index = (-2 * half..0).fold(0, |index, x| (index << 1 & keep) | column(x));
+        for new_x in 0..new_image.width() {
+            index = (index << 1 & keep) | column(new_x as i32);
+
+            if rule.apply(index) {
+                new_image.set(new_x, new_y);
+            }
         }
-        new_image.push(new_row);
     }
 
-    Image { contents: new_image, bg }
+    new_image
 }
 
-fn count_lit_pixels(image: &Image) -> usize {
-    image.contents.iter().flatten().filter(|c| **c == '#').count()
+/// Counts the lit pixels within the bounds of the image (ignoring the background).
+pub fn count_lit_pixels(image: &Image) -> usize {
+    image.rows.iter().flatten().map(|word| word.count_ones() as usize).sum()
 }
 
-fn parse(input: &str) -> (Image, String) {
+fn parse(input: &str) -> (Image, Rule) {
     let mut parts = input.split("\n\n");
-    let algorithm: String = String::from(parts.next().unwrap());
+    let algorithm = Rule::from_str(parts.next().unwrap()).unwrap();
     let image = Image::from_str(parts.next().unwrap()).unwrap();
 
     (image, algorithm)
 }
 
-pub fn part1(input: &str) -> usize {
-    let (image, algorithm) = parse(input);
-    assert_eq!(512, algorithm.len());
+fn enhance(mut image: Image, algorithm: &Rule, iterations: usize, dump_dir: Option<&Path>)
+    -> io::Result<usize> {
+    for i in 0..=iterations {
+        if let Some(dir) = dump_dir {
+            netpbm::write_to_dir(dir, &format!("step-{:02}.pbm", i), &image.to_pbm())?;
+        }
 
-    let image = process_image(&image, &algorithm);
-    let image = process_image(&image, &algorithm);
+        if i < iterations {
+            image = process_image(&image, algorithm);
+        }
+    }
 
-    count_lit_pixels(&image)
+    Ok(count_lit_pixels(&image))
 }
 
-pub fn part2(input: &str) -> usize {
-    let (mut image, algorithm) = parse(input);
-    assert_eq!(512, algorithm.len());
+pub fn part1(input: &str) -> usize {
+    let (image, algorithm) = parse(input);
+    enhance(image, &algorithm, 2, None).unwrap()
+}
 
-    let iterations = 50;
+pub fn part2(input: &str) -> usize {
+    let (image, algorithm) = parse(input);
+    enhance(image, &algorithm, 50, None).unwrap()
+}
 
-    let bar = ProgressBar::new(iterations);
-    for _ in 0..iterations {
-        image = process_image(&image, &algorithm);
-        bar.inc(1);
-    }
-    bar.finish();
+/// Runs the enhancement, optionally writing the image before and after each step to `dump_dir` as
+/// PBM files. If `image` is given it is read as a PBM and replaces the image from `input`.
+pub fn enhance_with(input: &str, image: Option<&str>, iterations: usize, dump_dir: Option<&Path>)
+    -> Result<usize, String> {
+    let (parsed, algorithm) = parse(input);
+    let image = match image {
+        Some(pbm) => Image::from_pbm(pbm)?,
+        None => parsed,
+    };
 
-    count_lit_pixels(&image)
+    enhance(image, &algorithm, iterations, dump_dir).map_err(|err| err.to_string())
 }
 
 #[cfg(test)]
@@ -164,10 +395,10 @@ mod test {
         assert_eq!(false, image.get(-1, -1));
         assert_eq!(false, image.get(5, 5));
 
-        image.bg = '#';
+        image.bg = true;
         assert_eq!(true, image.get(-1, -1));
         assert_eq!(true, image.get(5, 5));
-        image.bg = '.';
+        image.bg = false;
 
         assert_eq!(true, image.get(0, 0));
         assert_eq!(true, image.get(4, 4));
@@ -177,8 +408,9 @@ mod test {
 
     #[test]
     fn test_process() {
+        let algorithm = Rule::from_str(ALGORITHM).unwrap();
         let image = Image::from_str(IMAGE_INPUT).unwrap();
-        let image = process_image(&image, ALGORITHM);
+        let image = process_image(&image, &algorithm);
 
         let expected = Image::from_str("\
 .##.##.
@@ -191,9 +423,9 @@ mod test {
 
         assert_eq!(image.width(), expected.width());
         assert_eq!(image.height(), expected.height());
-        assert_eq!(image.contents, expected.contents);
+        assert_eq!(image, expected);
 
-        let image = process_image(&image, ALGORITHM);
+        let image = process_image(&image, &algorithm);
 
         let expected = Image::from_str("\
 .......#.
@@ -208,7 +440,144 @@ mod test {
 
         assert_eq!(image.width(), expected.width());
         assert_eq!(image.height(), expected.height());
-        assert_eq!(image.contents, expected.contents);
+        assert_eq!(image, expected);
+    }
+
+    #[test]
+    fn test_window_matches_get_num() {
+        let (image, algorithm) = parse(OTHER_EXAMPLE);
+        let image = process_image(&process_image(&image, &algorithm), &algorithm);
+        let next = process_image(&image, &algorithm);
+
+        for y in -1..=image.height() as i32 {
+            for x in -1..=image.width() as i32 {
+                let expected = algorithm.apply(image.get_num(x, y) as u64);
+                assert_eq!(expected, next.get(x + 1, y + 1), "at ({}, {})", x, y);
+            }
+        }
+    }
+
+    #[test]
+    fn test_wide_image() {
+        // Wider than a single word, so the window has to cross word boundaries.
+        let row = "#.".repeat(70);
+        let image = Image::from_str(&format!("{}\n{}", row, row)).unwrap();
+
+        assert_eq!(140, image.width());
+        assert_eq!(140, count_lit_pixels(&image));
+        assert_eq!(format!("{}\n{}\n", row, row), format!("{:?}", image));
+    }
+
+    #[test]
+    fn test_pbm_round_trip() {
+        let image = Image::from_str(IMAGE_INPUT).unwrap();
+        let pbm = image.to_pbm();
+
+        assert_eq!("P1\n# background: dark\n5 5\n10010\n10000\n11001\n00100\n00111\n", pbm);
+        assert_eq!(image, Image::from_pbm(&pbm).unwrap());
+
+        // Everything dark becomes lit, including the background.
+        let rule = Rule::from_fn(3, |index| index & 0b10000 == 0).unwrap();
+        let flashed = process_image(&Image::from_str("#").unwrap(), &rule);
+        assert_eq!("P1\n# background: lit\n3 3\n111\n101\n111\n", flashed.to_pbm());
+    }
+
+    #[test]
+    fn test_life_like_blinker() {
+        let rule = Rule::from_str("B3/S23").unwrap();
+        let image = Image::from_str(".#.\n.#.\n.#.").unwrap();
+
+        let image = process_image(&image, &rule);
+        assert_eq!(Image::from_str(".....\n.....\n.###.\n.....\n.....").unwrap(), image);
+
+        let image = process_image(&image, &rule);
+        assert_eq!(3, count_lit_pixels(&image));
+        assert_eq!(true, image.get(3, 2));
+        assert_eq!(true, image.get(3, 4));
+    }
+
+    #[test]
+    fn test_table_matches_life_like() {
+        // The 3x3 table equivalent to B3/S23.
+        let table: String = (0..512_u64)
+            .map(|index| {
+                let lit = (index >> 4) & 1 == 1;
+                let neighbours = index.count_ones() - lit as u32;
+                if neighbours == 3 || (lit && neighbours == 2) { '#' } else { '.' }
+            })
+            .collect();
+
+        let life = Rule::from_str("B3/S23").unwrap();
+        let table = Rule::from_str(&table).unwrap();
+
+        let (mut a, _) = parse(OTHER_EXAMPLE);
+        let (mut b, _) = parse(OTHER_EXAMPLE);
+        for _ in 0..5 {
+            a = process_image(&a, &life);
+            b = process_image(&b, &table);
+            assert_eq!(a, b);
+        }
+    }
+
+    #[test]
+    fn test_5x5_kernel() {
+        // Lit if anything in the 5x5 square is lit, so a single pixel grows into a square.
+        let rule = Rule::from_fn(5, |index| index != 0).unwrap();
+        let image = process_image(&Image::from_str("#").unwrap(), &rule);
+        assert_eq!(Image::from_str(&["#####"; 5].join("\n")).unwrap(), image);
+
+        // The same thing as a Life-like rule.
+        let counts: Vec<usize> = (0..25).collect();
+        let life = Rule::life_like(5, &counts[1..], &counts).unwrap();
+        let (image, _) = parse(OTHER_EXAMPLE);
+        assert_eq!(process_image(&image, &rule), process_image(&image, &life));
+    }
+
+    #[test]
+    fn test_life_like_syntax() {
+        let life = |kernel, birth: &[usize], survive: &[usize]| {
+            let (birth, survive) = match Rule::life_like(kernel, birth, survive).unwrap() {
+                Rule::LifeLike { birth, survive, .. } => (birth, survive),
+                Rule::Table { .. } => unreachable!(),
+            };
+            (kernel, birth, survive)
+        };
+        let parse = |s| match Rule::from_str(s).unwrap() {
+            Rule::LifeLike { kernel, birth, survive } => (kernel, birth, survive),
+            Rule::Table { .. } => panic!("{} parsed as a table", s),
+        };
+
+        assert_eq!(life(3, &[3], &[2, 3]), parse("B3/S23"));
+        assert_eq!(life(3, &[3], &[2, 3]), parse("B3/S2,3/K3"));
+        assert_eq!(life(5, &[3, 12], &[2, 3]), parse("B3,12/S23/K5"));
+        assert_eq!(life(7, &[12], &[]), parse("k7/b12,/s"));
+    }
+
+    #[test]
+    fn test_background_follows_rule() {
+        // Everything dark becomes lit, and everything lit becomes dark.
+        let rule = Rule::from_fn(3, |index| index & 0b10000 == 0).unwrap();
+        let image = process_image(&Image::from_str("#").unwrap(), &rule);
+
+        assert_eq!(true, image.get(-10, -10));
+        assert_eq!(Image::from_str("###\n#.#\n###").unwrap().rows, image.rows);
+        assert_eq!(false, process_image(&image, &rule).get(-10, -10));
+    }
+
+    #[test]
+    fn test_rule_errors() {
+        assert!(Rule::from_str("B3").is_err());
+        assert!(Rule::from_str("B3/Sx").is_err());
+        assert!(Rule::from_str("B3/S2,x").is_err());
+        assert!(Rule::from_str("B3/S23/K4").is_err());
+        assert!(Rule::from_str("B3/S23/Kx").is_err());
+        assert!(Rule::from_str("B3,12/S23").is_err());
+        assert!(Rule::from_str("#.#").is_err());
+        assert!(Rule::from_str(&"x".repeat(512)).is_err());
+        assert!(Rule::from_fn(4, |_| true).is_err());
+        assert!(Rule::from_fn(7, |_| true).is_err());
+        assert!(Rule::life_like(3, &[9], &[]).is_err());
+        assert!(Rule::life_like(7, &[48], &[]).is_ok());
     }
 
     #[test]
@@ -222,7 +591,6 @@ mod test {
 
     #[test]
     fn given_example_part2() {
-        // TODO: Optimize
         assert_eq!(3351, part2(INPUT));
     }
 
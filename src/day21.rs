@@ -1,35 +1,90 @@
-use std::cmp::min;
 use std::collections::HashMap;
-use crate::day21::Player::{Player1, Player2};
+use std::fmt;
+use std::fmt::Formatter;
+use sscanf::scanf;
+
+/// The parameters of a game of Dirac Dice.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct GameRules {
+    pub board_size: i32,
+    pub target_score: i32,
+    pub die_faces: i32,
+    pub rolls_per_turn: i32,
+}
+
+impl GameRules {
+    /// The rules for the practice game with the deterministic die.
+    pub const DETERMINISTIC: GameRules =
+        GameRules { board_size: 10, target_score: 1000, die_faces: 100, rolls_per_turn: 3 };
+
+    /// The rules for the game with the Dirac die.
+    pub const QUANTUM: GameRules =
+        GameRules { board_size: 10, target_score: 21, die_faces: 3, rolls_per_turn: 3 };
+
+    fn check(&self, game: &Game) -> Result<(), String> {
+        if self.board_size < 1 || self.target_score < 1 || self.die_faces < 1
+            || self.rolls_per_turn < 1 {
+            return Err(format!("Invalid rules: {:?}", self));
+        }
+
+        match game.starting_positions.iter().position(|p| *p > self.board_size) {
+            Some(player) => Err(format!("Player {} starts off the board at {}",
+                                        player + 1, game.starting_positions[player])),
+            None => Ok(()),
+        }
+    }
+
+    /// When a player rolls the Dirac die to see how far they go, each total happens in some number
+    /// of universes. Eg for three rolls of a three sided die, they get a 3 in 1 universe (1, 1, 1),
+    /// a 4 in 3 universes ((1, 1, 2), (1, 2, 1), (2, 1, 1)), and so on.
+    fn roll_frequencies(&self) -> Vec<(i32, u64)> {
+        let mut frequencies: HashMap<i32, u64> = HashMap::from([(0, 1)]);
+
+        for _ in 0..self.rolls_per_turn {
+            let mut next: HashMap<i32, u64> = HashMap::new();
+            for (total, frequency) in frequencies {
+                for face in 1..=self.die_faces {
+                    *next.entry(total + face).or_insert(0) += frequency;
+                }
+            }
+            frequencies = next;
+        }
+
+        let mut frequencies: Vec<(i32, u64)> = frequencies.into_iter().collect();
+        frequencies.sort();
+        frequencies
+    }
+}
 
 struct Die {
-    // Store the next roll as 0-99 instead of 1-100 to make maths a bit simpler.
+    faces: i32,
+    // Store the next roll as 0..faces instead of 1..=faces to make maths a bit simpler.
     next_roll_minus_one: i32,
     num_rolls: i32,
 }
 
 impl Die {
-    fn new() -> Die {
-        Die { next_roll_minus_one: 0, num_rolls: 0 }
+    fn new(faces: i32) -> Die {
+        Die { faces, next_roll_minus_one: 0, num_rolls: 0 }
     }
 
     fn roll(&mut self) -> i32 {
         let result = self.next_roll_minus_one;
 
         self.num_rolls += 1;
-        self.next_roll_minus_one = (self.next_roll_minus_one + 1) % 100;
+        self.next_roll_minus_one = (self.next_roll_minus_one + 1) % self.faces;
 
         result + 1
     }
 
-    fn sum_three_rolls(&mut self) -> i32 {
-        self.roll() + self.roll() + self.roll()
+    fn sum_rolls(&mut self, rolls: i32) -> i32 {
+        (0..rolls).map(|_| self.roll()).sum()
     }
 }
 
 #[derive(Debug, PartialEq, Eq, Hash, Ord, PartialOrd, Copy, Clone)]
 struct PlayerState {
-    // Store the position as 0-9 instead of 1-10 to make maths a bit simpler.
+    // Store the position as 0..board_size instead of 1..=board_size to make maths a bit simpler.
     position_minus_one: i32,
     score: i32,
 }
@@ -42,8 +97,8 @@ impl PlayerState {
         }
     }
 
-    fn take_turn(&self, rolls: i32) -> PlayerState {
-        let new_position_minus_one = (self.position_minus_one + rolls) % 10;
+    fn take_turn(&self, rolls: i32, board_size: i32) -> PlayerState {
+        let new_position_minus_one = (self.position_minus_one + rolls) % board_size;
         PlayerState {
             position_minus_one: new_position_minus_one,
             score: self.score + new_position_minus_one + 1,
@@ -51,130 +106,322 @@ impl PlayerState {
     }
 }
 
-#[derive(Debug, PartialEq, Eq, Hash, Ord, PartialOrd, Copy, Clone)]
+#[derive(Debug, PartialEq, Eq, Hash, Ord, PartialOrd, Clone)]
 struct GameState {
-    player1: PlayerState,
-    player2: PlayerState,
-}
-
-#[derive(Copy, Clone)]
-enum Player {
-    Player1,
-    Player2,
+    players: Vec<PlayerState>,
 }
 
 impl GameState {
-    fn new(p1_pos: i32, p2_pos: i32) -> GameState {
+    fn new(game: &Game) -> GameState {
         GameState {
-            player1: PlayerState::new(p1_pos),
-            player2: PlayerState::new(p2_pos),
+            players: game.starting_positions.iter().map(|p| PlayerState::new(*p)).collect(),
         }
     }
 
-    fn has_been_won(&self, required_points: i32) -> bool {
-        self.player1.score >= required_points
-            || self.player2.score >= required_points
+    fn winner(&self, required_points: i32) -> Option<usize> {
+        self.players.iter().position(|player| player.score >= required_points)
     }
 
-    fn losing_player_score(&self) -> i32 {
-        min(self.player1.score, self.player2.score)
+    fn take_turn(&self, player: usize, rolls: i32, board_size: i32) -> GameState {
+        let mut next = self.clone();
+        next.players[player] = self.players[player].take_turn(rolls, board_size);
+        next
     }
+}
 
-    fn take_turn(&self, player: Player, rolls: i32) -> GameState {
-        match player {
-            Player::Player1 => {
-                GameState {
-                    player1: self.player1.take_turn(rolls),
-                    ..*self
-                }
-            }
-            Player::Player2 => {
-                GameState {
-                    player2: self.player2.take_turn(rolls),
-                    ..*self
-                }
-            }
+/// The starting position of each player, in turn order.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Game {
+    pub starting_positions: Vec<i32>,
+}
+
+pub fn parse(input: &str) -> Result<Game, String> {
+    let mut starting_positions = Vec::new();
+
+    for line in input.lines().filter(|line| !line.trim().is_empty()) {
+        let (player, position) = scanf!(line.trim(), "Player {} starting position: {}", usize, i32)
+            .ok_or(format!("Could not parse line: {}", line))?;
+
+        if player != starting_positions.len() + 1 {
+            return Err(format!("Expected player {}, found player {}",
+                               starting_positions.len() + 1, player));
+        }
+        if position < 1 {
+            return Err(format!("Player {} starts off the board at {}", player, position));
         }
+
+        starting_positions.push(position);
     }
+
+    if starting_positions.is_empty() {
+        return Err(String::from("No players found"));
+    }
+
+    Ok(Game { starting_positions })
 }
 
-pub fn part1(p1_pos: i32, p2_pos: i32) -> i32 {
-    let mut game = GameState::new(p1_pos, p2_pos);
-    let mut die = Die::new();
+pub fn play_deterministic(game: &Game, rules: &GameRules) -> Result<i32, String> {
+    rules.check(game)?;
 
-    loop {
-        game = game.take_turn(Player1, die.sum_three_rolls());
-        if game.has_been_won(1000) { break; }
+    let mut state = GameState::new(game);
+    let mut die = Die::new(rules.die_faces);
 
-        game = game.take_turn(Player2, die.sum_three_rolls());
-        if game.has_been_won(1000) { break; }
+    for current in (0..state.players.len()).cycle() {
+        state = state.take_turn(current, die.sum_rolls(rules.rolls_per_turn), rules.board_size);
+        if state.winner(rules.target_score).is_some() { break; }
     }
 
-    die.num_rolls * game.losing_player_score()
+    let losing_player_score = state.players.iter().map(|player| player.score).min().unwrap();
+    Ok(die.num_rolls * losing_player_score)
 }
 
-type UniverseFrequencies = HashMap<GameState, u64>;
+pub fn part1(game: &Game) -> Result<i32, String> {
+    play_deterministic(game, &GameRules::DETERMINISTIC)
+}
+
+/// What happens from some state of the quantum game onwards.
+#[derive(Debug, Clone)]
+struct Outcome {
+    /// The number of universes in which each player wins.
+    wins: Vec<u128>,
+    /// The chance of each player winning. This isn't the same as their share of the universes,
+    /// since games that end sooner split into fewer universes.
+    win_probabilities: Vec<f64>,
+    expected_turns: f64,
+}
+
+struct QuantumSolver<'a> {
+    rules: &'a GameRules,
+    roll_frequencies: Vec<(i32, u64)>,
+    total_frequency: u64,
+    cache: HashMap<(GameState, usize), Outcome>,
+}
+
+impl QuantumSolver<'_> {
+    fn new(rules: &GameRules) -> QuantumSolver<'_> {
+        let roll_frequencies = rules.roll_frequencies();
+        let total_frequency = roll_frequencies.iter().map(|(_, frequency)| frequency).sum();
+
+        QuantumSolver { rules, roll_frequencies, total_frequency, cache: HashMap::new() }
+    }
+
+    /// The outcome of the game from `state`, with `player` about to roll.
+    fn solve(&mut self, state: &GameState, player: usize) -> Outcome {
+        let key = (state.clone(), player);
+        if let Some(outcome) = self.cache.get(&key) {
+            return outcome.clone();
+        }
+
+        let num_players = state.players.len();
+        let mut outcome = Outcome {
+            wins: vec![0; num_players],
+            win_probabilities: vec![0.0; num_players],
+            expected_turns: 0.0,
+        };
 
-// When a player rolls the dice three times to see how far they go...
-const ROLL_FREQUENCIES: [(u8, u8); 7] = [
-    (3, 1),  // they get a 3 in 1 universe (1, 1, 1).
-    (4, 3),  // they get a 4 in 3 universes ((1, 1, 2), (1, 2, 1), (2, 1, 1)).
-    (5, 6),  // ...
-    (6, 7),
-    (7, 6),
-    (8, 3),
-    (9, 1),
-];
+        for i in 0..self.roll_frequencies.len() {
+            let (roll, frequency) = self.roll_frequencies[i];
+            let probability = frequency as f64 / self.total_frequency as f64;
+            let next = state.take_turn(player, roll, self.rules.board_size);
 
-fn take_turn(universes: &UniverseFrequencies, player: Player) -> UniverseFrequencies {
-    let mut next_universes : HashMap<GameState, u64> = HashMap::new();
+            outcome.expected_turns += probability;
 
-    for (universe, universe_frequency) in universes.iter() {
-        // Don't continue once a player has won.
-        if universe.has_been_won(21) {
-            *next_universes.entry(universe.clone()).or_insert(0)
-                += universe_frequency;
-            continue;
+            if next.winner(self.rules.target_score).is_some() {
+                outcome.wins[player] += frequency as u128;
+                outcome.win_probabilities[player] += probability;
+                continue;
+            }
+
+            let rest = self.solve(&next, (player + 1) % num_players);
+            for p in 0..num_players {
+                outcome.wins[p] += frequency as u128 * rest.wins[p];
+                outcome.win_probabilities[p] += probability * rest.win_probabilities[p];
+            }
+            outcome.expected_turns += probability * rest.expected_turns;
         }
 
-        for (roll, roll_frequency) in &ROLL_FREQUENCIES {
-            let new_state = universe.take_turn(player, *roll as i32);
-            *next_universes.entry(new_state).or_insert(0)
-                += universe_frequency * (*roll_frequency as u64);
+        self.cache.insert(key, outcome.clone());
+        outcome
+    }
+}
+
+fn solve_quantum(game: &Game, rules: &GameRules) -> Result<Outcome, String> {
+    rules.check(game)?;
+    Ok(QuantumSolver::new(rules).solve(&GameState::new(game), 0))
+}
+
+/// Returns the number of universes in which each player wins.
+pub fn play_quantum(game: &Game, rules: &GameRules) -> Result<Vec<u128>, String> {
+    solve_quantum(game, rules).map(|outcome| outcome.wins)
+}
+
+pub fn part2(game: &Game) -> Result<Vec<u128>, String> {
+    play_quantum(game, &GameRules::QUANTUM)
+}
+
+#[derive(Debug)]
+pub struct QuantumReport {
+    pub wins: Vec<u128>,
+    pub win_probabilities: Vec<f64>,
+    /// The expected number of turns in a game, counting each player's turn separately.
+    pub expected_turns: f64,
+}
+
+impl fmt::Display for QuantumReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        for (player, (wins, probability)) in
+            self.wins.iter().zip(self.win_probabilities.iter()).enumerate() {
+            writeln!(f, "Player {} wins in {} universes, with probability {:.6}",
+                     player + 1, wins, probability)?;
         }
+        write!(f, "Expected number of turns: {:.6}", self.expected_turns)
     }
+}
 
-    next_universes
+pub fn quantum_report(game: &Game, rules: &GameRules) -> Result<QuantumReport, String> {
+    solve_quantum(game, rules).map(|outcome| QuantumReport {
+        wins: outcome.wins,
+        win_probabilities: outcome.win_probabilities,
+        expected_turns: outcome.expected_turns,
+    })
 }
 
-fn all_won(universes: &UniverseFrequencies) -> bool {
-    universes.iter().all(|(u, _)| u.has_been_won(21))
+/// The best choice for the player about to move in one state of the choice variant.
+#[derive(Debug, PartialEq, Clone)]
+pub struct PolicyEntry {
+    /// The player about to move, counting from 1.
+    pub player: usize,
+    pub positions: Vec<i32>,
+    pub scores: Vec<i32>,
+    /// How many times the player should roll.
+    pub rolls: i32,
+    /// Each player's chance of winning from here, if everyone plays optimally.
+    pub win_probabilities: Vec<f64>,
 }
 
-pub fn part2(p1_pos: i32, p2_pos: i32) -> (u64, u64) {
-    let mut universes: UniverseFrequencies = HashMap::new();
-    universes.insert(GameState::new(p1_pos, p2_pos), 1);
+#[derive(Debug)]
+pub struct OptimalPlay {
+    pub win_probabilities: Vec<f64>,
+    /// The best choice in every state reachable from the start.
+    pub policy: Vec<PolicyEntry>,
+}
 
-    loop {
-        universes = take_turn(&universes, Player1);
-        if all_won(&universes) { break; }
+impl OptimalPlay {
+    /// Writes the policy as CSV, with one row per state.
+    pub fn policy_table(&self) -> String {
+        let players = self.win_probabilities.len();
+        let mut header = vec![String::from("player")];
+        header.extend((1..=players).map(|p| format!("position{}", p)));
+        header.extend((1..=players).map(|p| format!("score{}", p)));
+        header.push(String::from("rolls"));
+        header.extend((1..=players).map(|p| format!("p{}_wins", p)));
+
+        let mut output = header.join(",");
+        output.push('\n');
+
+        for entry in &self.policy {
+            let mut row = vec![entry.player.to_string()];
+            row.extend(entry.positions.iter().map(|p| p.to_string()));
+            row.extend(entry.scores.iter().map(|s| s.to_string()));
+            row.push(entry.rolls.to_string());
+            row.extend(entry.win_probabilities.iter().map(|p| format!("{:.6}", p)));
+
+            output.push_str(&row.join(","));
+            output.push('\n');
+        }
 
-        universes = take_turn(&universes, Player2);
-        if all_won(&universes) { break; }
+        output
     }
+}
+
+/// Solves the variant where, on each turn, a player chooses to roll between 1 and
+/// `rules.rolls_per_turn` times, moving by the total. Every player tries to maximise their own
+/// chance of winning, and a tie between choices goes to the fewest rolls.
+struct ChoiceSolver<'a> {
+    rules: &'a GameRules,
+    // The probability of each total, for each number of rolls.
+    roll_probabilities: Vec<(i32, Vec<(i32, f64)>)>,
+    // The best number of rolls and the resulting win probabilities for each state.
+    cache: HashMap<(GameState, usize), (i32, Vec<f64>)>,
+}
 
-    let mut p1_wins: u64 = 0;
-    let mut p2_wins: u64 = 0;
+impl ChoiceSolver<'_> {
+    fn new(rules: &GameRules) -> ChoiceSolver<'_> {
+        let roll_probabilities = (1..=rules.rolls_per_turn)
+            .map(|rolls| {
+                let frequencies = GameRules { rolls_per_turn: rolls, ..rules.clone() }
+                    .roll_frequencies();
+                let total: u64 = frequencies.iter().map(|(_, frequency)| frequency).sum();
+
+                let probabilities = frequencies.iter()
+                    .map(|(roll, frequency)| (*roll, *frequency as f64 / total as f64))
+                    .collect();
+                (rolls, probabilities)
+            })
+            .collect();
+
+        ChoiceSolver { rules, roll_probabilities, cache: HashMap::new() }
+    }
 
-    for (universe, frequency) in universes.iter() {
-        if universe.player1.score > universe.player2.score {
-            p1_wins += frequency;
-        } else {
-            p2_wins += frequency;
+    fn solve(&mut self, state: &GameState, player: usize) -> (i32, Vec<f64>) {
+        let key = (state.clone(), player);
+        if let Some(result) = self.cache.get(&key) {
+            return result.clone();
         }
+
+        let num_players = state.players.len();
+        let mut best: Option<(i32, Vec<f64>)> = None;
+
+        for choice in 0..self.roll_probabilities.len() {
+            let rolls = self.roll_probabilities[choice].0;
+            let mut win_probabilities = vec![0.0; num_players];
+
+            for i in 0..self.roll_probabilities[choice].1.len() {
+                let (roll, probability) = self.roll_probabilities[choice].1[i];
+                let next = state.take_turn(player, roll, self.rules.board_size);
+
+                if next.winner(self.rules.target_score).is_some() {
+                    win_probabilities[player] += probability;
+                    continue;
+                }
+
+                let (_, rest) = self.solve(&next, (player + 1) % num_players);
+                for p in 0..num_players {
+                    win_probabilities[p] += probability * rest[p];
+                }
+            }
+
+            if best.as_ref().is_none_or(|(_, b)| win_probabilities[player] > b[player]) {
+                best = Some((rolls, win_probabilities));
+            }
+        }
+
+        let best = best.unwrap();
+        self.cache.insert(key, best.clone());
+        best
     }
+}
 
-    (p1_wins, p2_wins)
+pub fn solve_choice_variant(game: &Game, rules: &GameRules) -> Result<OptimalPlay, String> {
+    rules.check(game)?;
+
+    let mut solver = ChoiceSolver::new(rules);
+    let (_, win_probabilities) = solver.solve(&GameState::new(game), 0);
+
+    let mut policy: Vec<PolicyEntry> = solver.cache.into_iter()
+        .map(|((state, player), (rolls, win_probabilities))| PolicyEntry {
+            player: player + 1,
+            positions: state.players.iter().map(|p| p.position_minus_one + 1).collect(),
+            scores: state.players.iter().map(|p| p.score).collect(),
+            rolls,
+            win_probabilities,
+        })
+        .collect();
+    policy.sort_by(|a, b| {
+        (&a.scores, a.player, &a.positions).cmp(&(&b.scores, b.player, &b.positions))
+    });
+
+    Ok(OptimalPlay { win_probabilities, policy })
 }
 
 #[cfg(test)]
@@ -184,6 +431,7 @@ mod test {
     #[test]
     fn test_die() {
         let mut die = Die {
+            faces: 100,
             next_roll_minus_one: 97,
             num_rolls: 0,
         };
@@ -193,29 +441,297 @@ mod test {
         assert_eq!(100, die.roll());
         assert_eq!(1, die.roll());
 
-        let mut die = Die::new();
-        assert_eq!(6, die.sum_three_rolls());
-        assert_eq!(15, die.sum_three_rolls());
-        assert_eq!(24, die.sum_three_rolls());
+        let mut die = Die::new(100);
+        assert_eq!(6, die.sum_rolls(3));
+        assert_eq!(15, die.sum_rolls(3));
+        assert_eq!(24, die.sum_rolls(3));
+
+        let mut die = Die::new(6);
+        assert_eq!(15, die.sum_rolls(5));
+        assert_eq!(6 + 1 + 2 + 3 + 4 + 5 + 6, die.sum_rolls(7));
+    }
+
+    const EXAMPLE: &str = "\
+Player 1 starting position: 4
+Player 2 starting position: 8";
+
+    const CHALLENGE: &str = "\
+Player 1 starting position: 6
+Player 2 starting position: 9";
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(Game { starting_positions: vec![4, 8] }, parse(EXAMPLE).unwrap());
+        assert_eq!(Game { starting_positions: vec![1, 2, 3] }, parse("\
+Player 1 starting position: 1
+Player 2 starting position: 2
+Player 3 starting position: 3
+").unwrap());
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(parse("").is_err());
+        assert!(parse("Player 1 starting position: x").is_err());
+        assert!(parse("Player 1 starting position: 0").is_err());
+        assert!(parse("Player 2 starting position: 4").is_err());
+        assert!(parse("Player 1 starting position: 4\nPlayer 1 starting position: 8").is_err());
+        assert!(parse("Player 1 starts at 4").is_err());
     }
 
     #[test]
     fn given_example_part1() {
-        assert_eq!(739785, part1(4, 8));
+        assert_eq!(Ok(739785), part1(&parse(EXAMPLE).unwrap()));
     }
 
     #[test]
     fn challenge_part1() {
-        assert_eq!(925605, part1(6, 9));
+        assert_eq!(Ok(925605), part1(&parse(CHALLENGE).unwrap()));
     }
 
     #[test]
     fn given_example_part2() {
-        assert_eq!((444356092776315, 341960390180808), part2(4, 8));
+        assert_eq!(Ok(vec![444356092776315, 341960390180808]), part2(&parse(EXAMPLE).unwrap()));
     }
 
     #[test]
     fn challenge_part2() {
-        assert_eq!((486638407378784, 413013330504401), part2(6, 9));
+        assert_eq!(Ok(vec![486638407378784, 413013330504401]), part2(&parse(CHALLENGE).unwrap()));
+    }
+
+    #[test]
+    fn test_roll_frequencies() {
+        assert_eq!(vec![(3, 1), (4, 3), (5, 6), (6, 7), (7, 6), (8, 3), (9, 1)],
+                   GameRules::QUANTUM.roll_frequencies());
+
+        let rules = GameRules { die_faces: 2, rolls_per_turn: 2, ..GameRules::QUANTUM };
+        assert_eq!(vec![(2, 1), (3, 2), (4, 1)], rules.roll_frequencies());
+    }
+
+    #[test]
+    fn test_rule_errors() {
+        let game = parse(EXAMPLE).unwrap();
+        let small_board = GameRules { board_size: 6, ..GameRules::QUANTUM };
+        assert!(play_quantum(&game, &small_board).is_err());
+
+        let no_die = GameRules { die_faces: 0, ..GameRules::DETERMINISTIC };
+        assert!(play_deterministic(&game, &no_die).is_err());
+    }
+
+    /// Plays the quantum game a turn at a time, tracking how many universes are in each state,
+    /// like the original part 2 did. Returns the wins for each player and the expected number of
+    /// turns.
+    fn expand_turn_by_turn(positions: &[i32], rules: &GameRules) -> (Vec<u128>, f64) {
+        // Every sequence of rolls in a turn, rather than the grouped roll frequencies.
+        let sequences = rules.die_faces.pow(rules.rolls_per_turn as u32);
+        let totals: Vec<i32> = (0..sequences)
+            .map(|mut sequence| (0..rules.rolls_per_turn).map(|_| {
+                let face = sequence % rules.die_faces + 1;
+                sequence /= rules.die_faces;
+                face
+            }).sum())
+            .collect();
+
+        let start: Vec<(i32, i32)> = positions.iter().map(|p| (*p, 0)).collect();
+        let mut universes: HashMap<Vec<(i32, i32)>, u128> = HashMap::from([(start, 1)]);
+        let mut wins = vec![0; positions.len()];
+        let mut expected_turns = 0.0;
+        let mut turn = 0;
+
+        while !universes.is_empty() {
+            let player = turn % positions.len();
+            turn += 1;
+
+            let mut next: HashMap<Vec<(i32, i32)>, u128> = HashMap::new();
+            let mut won = 0;
+            for (universe, count) in universes {
+                for total in &totals {
+                    let mut universe = universe.clone();
+                    let (position, score) = &mut universe[player];
+                    *position = (*position + total - 1) % rules.board_size + 1;
+                    *score += *position;
+
+                    if *score >= rules.target_score {
+                        won += count;
+                    } else {
+                        *next.entry(universe).or_insert(0) += count;
+                    }
+                }
+            }
+
+            wins[player] += won;
+            expected_turns += turn as f64 * won as f64 / (sequences as f64).powi(turn as i32);
+            universes = next;
+        }
+
+        (wins, expected_turns)
+    }
+
+    #[test]
+    fn three_players() {
+        let game = Game { starting_positions: vec![4, 8, 1] };
+
+        // With rolls of 6, 15, 24, 33..., the players move to 10, 3, 5, 3, 5, 6, 3, 4, 4, 10, so
+        // player 1 wins with 26 after 30 rolls, and player 2 has the lowest score of 12.
+        let rules = GameRules { target_score: 20, ..GameRules::DETERMINISTIC };
+        assert_eq!(Ok(30 * 12), play_deterministic(&game, &rules));
+
+        let rules = GameRules { target_score: 10, ..GameRules::QUANTUM };
+        let wins = play_quantum(&game, &rules).unwrap();
+        assert_eq!(vec![1932136999, 2531115256, 4095622876], wins);
+
+        for target_score in [5, 10] {
+            let rules = GameRules { target_score, ..GameRules::QUANTUM };
+            assert_eq!(expand_turn_by_turn(&[4, 8, 1], &rules).0,
+                       play_quantum(&game, &rules).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_report() {
+        let report = quantum_report(&parse(EXAMPLE).unwrap(), &GameRules::QUANTUM).unwrap();
+        assert_eq!(vec![444356092776315, 341960390180808], report.wins);
+
+        let total: f64 = report.win_probabilities.iter().sum();
+        assert!((total - 1.0).abs() < 1e-9);
+
+        assert!((report.expected_turns - 6.9187117).abs() < 1e-6);
+
+        let (wins, expected_turns) = expand_turn_by_turn(&[4, 8], &GameRules::QUANTUM);
+        assert_eq!(report.wins, wins);
+        assert!((report.expected_turns - expected_turns).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_report_single_turn() {
+        // With a target of 1 the first player always wins on their first turn.
+        let rules = GameRules { target_score: 1, ..GameRules::QUANTUM };
+        let report = quantum_report(&parse(EXAMPLE).unwrap(), &rules).unwrap();
+
+        assert_eq!(vec![27, 0], report.wins);
+        assert!((report.win_probabilities[0] - 1.0).abs() < 1e-9);
+        assert_eq!(0.0, report.win_probabilities[1]);
+        assert!((report.expected_turns - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn large_target_doesnt_overflow() {
+        let rules = GameRules { target_score: 40, ..GameRules::QUANTUM };
+        let wins = play_quantum(&parse(EXAMPLE).unwrap(), &rules).unwrap();
+        assert!(wins.iter().sum::<u128>() > u64::MAX as u128);
+    }
+
+    #[test]
+    fn choice_variant_without_choice() {
+        // With only one roll per turn there's nothing to choose, so this is the quantum game.
+        let rules = GameRules { rolls_per_turn: 1, target_score: 15, ..GameRules::QUANTUM };
+        let game = parse(EXAMPLE).unwrap();
+
+        let optimal = solve_choice_variant(&game, &rules).unwrap();
+        let report = quantum_report(&game, &rules).unwrap();
+
+        for (a, b) in optimal.win_probabilities.iter().zip(report.win_probabilities.iter()) {
+            assert!((a - b).abs() < 1e-9);
+        }
+        assert!(optimal.policy.iter().all(|entry| entry.rolls == 1));
+    }
+
+    #[test]
+    fn choice_variant() {
+        let optimal = solve_choice_variant(&parse(EXAMPLE).unwrap(), &GameRules::QUANTUM).unwrap();
+
+        let total: f64 = optimal.win_probabilities.iter().sum();
+        assert!((total - 1.0).abs() < 1e-9);
+
+        // The starting state comes first, since it has the lowest scores.
+        let start = &optimal.policy[0];
+        assert_eq!((1, vec![4, 8], vec![0, 0]), (start.player, start.positions.clone(),
+                                                 start.scores.clone()));
+        assert_eq!(optimal.win_probabilities, start.win_probabilities);
+
+        assert!(optimal.policy.iter().all(|entry| (1..=3).contains(&entry.rolls)));
+        assert!(optimal.policy.iter().any(|entry| entry.rolls != 3));
     }
-}
\ No newline at end of file
+
+    /// Plays every sequence of die faces for every choice of rolls, and picks the choice that
+    /// gives the player about to move the best chance of winning, preferring fewer rolls.
+    fn minimax(positions: &[i32], scores: &[i32], player: usize, rules: &GameRules,
+               cache: &mut HashMap<(Vec<i32>, Vec<i32>, usize), (i32, Vec<f64>)>)
+        -> (i32, Vec<f64>) {
+        let key = (positions.to_vec(), scores.to_vec(), player);
+        if let Some(result) = cache.get(&key) {
+            return result.clone();
+        }
+
+        let mut best: Option<(i32, Vec<f64>)> = None;
+        for rolls in 1..=rules.rolls_per_turn {
+            let mut totals = vec![0];
+            for _ in 0..rolls {
+                totals = totals.iter()
+                    .flat_map(|total| (1..=rules.die_faces).map(move |face| total + face))
+                    .collect();
+            }
+
+            let mut win_probabilities = vec![0.0; positions.len()];
+            for total in &totals {
+                let probability = 1.0 / totals.len() as f64;
+                let (mut positions, mut scores) = (positions.to_vec(), scores.to_vec());
+                positions[player] = (positions[player] + total - 1) % rules.board_size + 1;
+                scores[player] += positions[player];
+
+                if scores[player] >= rules.target_score {
+                    win_probabilities[player] += probability;
+                    continue;
+                }
+
+                let next = (player + 1) % positions.len();
+                let (_, rest) = minimax(&positions, &scores, next, rules, cache);
+                for (p, rest) in rest.iter().enumerate() {
+                    win_probabilities[p] += probability * rest;
+                }
+            }
+
+            if best.as_ref().is_none_or(|(_, b)| win_probabilities[player] > b[player] + 1e-12) {
+                best = Some((rolls, win_probabilities));
+            }
+        }
+
+        let best = best.unwrap();
+        cache.insert(key, best.clone());
+        best
+    }
+
+    #[test]
+    fn choice_variant_matches_minimax() {
+        let rules = GameRules { target_score: 10, ..GameRules::QUANTUM };
+        let optimal = solve_choice_variant(&parse(EXAMPLE).unwrap(), &rules).unwrap();
+        let mut cache = HashMap::new();
+
+        let (_, expected) = minimax(&[4, 8], &[0, 0], 0, &rules, &mut cache);
+        for (a, b) in optimal.win_probabilities.iter().zip(expected.iter()) {
+            assert!((a - b).abs() < 1e-9);
+        }
+
+        assert!(optimal.policy.iter().any(|entry| entry.rolls != 3));
+        for entry in &optimal.policy {
+            let (rolls, expected) = minimax(&entry.positions, &entry.scores, entry.player - 1,
+                                            &rules, &mut cache);
+            assert_eq!(rolls, entry.rolls, "{:?}", entry);
+            for (a, b) in entry.win_probabilities.iter().zip(expected.iter()) {
+                assert!((a - b).abs() < 1e-9, "{:?}", entry);
+            }
+        }
+    }
+
+    #[test]
+    fn choice_variant_table() {
+        // With a target of 1, the first player wins whatever they do, so rolls once.
+        let rules = GameRules { target_score: 1, ..GameRules::QUANTUM };
+        let optimal = solve_choice_variant(&parse(EXAMPLE).unwrap(), &rules).unwrap();
+
+        assert_eq!("\
+player,position1,position2,score1,score2,rolls,p1_wins,p2_wins
+1,4,8,0,0,1,1.000000,0.000000
+", optimal.policy_table());
+    }
+}
@@ -1,111 +1,88 @@
 use std::cmp::{max, min};
-use std::collections::HashSet;
-use sscanf::scanf;
+use std::collections::{BTreeSet, HashMap};
 
-pub fn part1(input: &str) -> usize {
-    let mut state = [[[false; 101]; 101]; 101];
+pub type Point<const N: usize> = [i64; N];
 
-    for line in input.lines() {
-        let (c, x1, x2, y1, y2, z1, z2) =
-            scanf!(line, "{} x={}..{},y={}..{},z={}..{}",
-                String, i32, i32, i32, i32, i32, i32).unwrap();
-
-        if x1 > 50 { continue; }
-        if x2 < -50 { continue; }
-
-        if y1 > 50 { continue; }
-        if y2 < -50 { continue; }
-
-        if z1 > 50 { continue; }
-        if z2 < -50 { continue; }
-
-        let x1 = max(x1, -50);
-        let x2 = min(x2, 50);
-
-        let y1 = max(y1, -50);
-        let y2 = min(y2, 50);
-
-        let z1 = max(z1, -50);
-        let z2 = min(z2, 50);
+/// A cuboid in `N` dimensions, including both ends of the range along each axis.
+#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone)]
+pub struct Cuboid<const N: usize> {
+    ranges: [Range; N],
+}
 
-        for x in x1..(x2+1) {
-            for y in y1..(y2+1) {
-                for z in z1..(z2+1) {
-                    let x = (x + 50) as usize;
-                    let y = (y + 50) as usize;
-                    let z = (z + 50) as usize;
+pub type Cube = Cuboid<3>;
 
-                    state[x][y][z] = c == "on";
-                }
-            }
-        }
+impl Cube {
+    pub fn new(x1: i64, x2: i64, y1: i64, y2: i64, z1: i64, z2: i64) -> Cube {
+        Cuboid { ranges: [(x1, x2), (y1, y2), (z1, z2)] }
     }
-
-    state.iter().flatten().flatten().filter(|e| **e).count()
 }
 
-struct Point {
-    x: i64, y: i64, z: i64,
-}
+impl<const N: usize> Cuboid<N> {
+    pub fn from_ranges(ranges: [Range; N]) -> Cuboid<N> {
+        Cuboid { ranges }
+    }
 
-impl Point {
-    fn new(x: i64, y: i64, z: i64) -> Point { Point { x, y, z }}
-}
+    pub fn ranges(&self) -> &[Range; N] {
+        &self.ranges
+    }
 
-#[derive(Debug, PartialEq, Eq, Copy, Clone)]
-struct Cube {
-    x1: i64, x2: i64,
-    y1: i64, y2: i64,
-    z1: i64, z2: i64,
-}
+    pub fn contains(&self, point: &Point<N>) -> bool {
+        self.ranges.iter().zip(point.iter()).all(|((start, end), p)| start <= p && p <= end)
+    }
 
-impl Cube {
-    fn new(x1: i64, x2: i64, y1: i64, y2: i64, z1: i64, z2: i64) -> Cube {
-        Cube { x1, x2, y1, y2, z1, z2 }
-    }
-
-    fn get_point(&self, point_index: usize) -> Point {
-        match point_index {
-            0 => Point::new(self.x1, self.y1, self.z1),
-            1 => Point::new(self.x1, self.y1, self.z2),
-            2 => Point::new(self.x1, self.y2, self.z1),
-            3 => Point::new(self.x1, self.y2, self.z2),
-            4 => Point::new(self.x2, self.y1, self.z1),
-            5 => Point::new(self.x2, self.y1, self.z2),
-            6 => Point::new(self.x2, self.y2, self.z1),
-            7 => Point::new(self.x2, self.y2, self.z2),
-            _ => panic!("Unknown point index"),
-        }
+    pub fn contains_cuboid(&self, other: &Cuboid<N>) -> bool {
+        self.ranges.iter().zip(other.ranges.iter())
+            .all(|(a, b)| a.0 <= b.0 && b.1 <= a.1)
     }
 
-    fn contains(&self, point: &Point) -> bool {
-        point.x >= self.x1 && point.x <= self.x2
-            && point.y >= self.y1 && point.y <= self.y2
-            && point.z >= self.z1 && point.z <= self.z2
+    pub fn volume(&self) -> u128 {
+        self.ranges.iter().map(|(start, end)| (end + 1 - start) as u128).product()
     }
 
-    fn contains_cube(&self, other: &Cube) -> bool {
-        for i in 0..8 {
-            if !self.contains(&other.get_point(i)) {
-                return false;
+    /// Cuts `other` out of this cuboid, returning what's left as disjoint cuboids (at most two
+    /// per axis).
+    pub fn subtract(&self, other: &Cuboid<N>) -> Vec<Cuboid<N>> {
+        let overlap = match Cuboid::intersection(self, other) {
+            Some(overlap) => overlap,
+            None => return vec![*self],
+        };
+
+        let mut pieces = Vec::new();
+        let mut rest = *self;
+
+        // Slice off the parts on either side of the overlap along each axis in turn, each time
+        // narrowing what's left down to the overlap along that axis.
+        for axis in 0..N {
+            let (start, end) = rest.ranges[axis];
+            let (overlap_start, overlap_end) = overlap.ranges[axis];
+
+            if start < overlap_start {
+                let mut piece = rest;
+                piece.ranges[axis] = (start, overlap_start - 1);
+                pieces.push(piece);
             }
+            if overlap_end < end {
+                let mut piece = rest;
+                piece.ranges[axis] = (overlap_end + 1, end);
+                pieces.push(piece);
+            }
+
+            rest.ranges[axis] = overlap.ranges[axis];
         }
-        return true;
-    }
 
-    fn volume(&self) -> u64 {
-        ((self.x2 + 1 - self.x1) * (self.y2 + 1 - self.y1) * (self.z2 + 1 - self.z1)) as u64
+        pieces
     }
 
-    fn intersection(a: &Cube, b: &Cube) -> Option<Cube> {
-        let (x1, x2) = range_overlap(&(a.x1, a.x2), &(b.x1, b.x2))?;
-        let (y1, y2) = range_overlap(&(a.y1, a.y2), &(b.y1, b.y2))?;
-        let (z1, z2) = range_overlap(&(a.z1, a.z2), &(b.z1, b.z2))?;
-        Some(Cube::new(x1, x2, y1, y2, z1, z2))
+    pub fn intersection(a: &Cuboid<N>, b: &Cuboid<N>) -> Option<Cuboid<N>> {
+        let mut ranges = a.ranges;
+        for (range, other) in ranges.iter_mut().zip(b.ranges.iter()) {
+            *range = range_overlap(range, other)?;
+        }
+        Some(Cuboid { ranges })
     }
 }
 
-type Range = (i64, i64);
+pub type Range = (i64, i64);
 
 fn range_overlap(a: &Range, b: &Range) -> Option<Range> {
     // a:  -----------
@@ -124,99 +101,279 @@ fn range_overlap(a: &Range, b: &Range) -> Option<Range> {
     Some((max(a.0, b.0), min(a.1, b.1)))
 }
 
-/// Gives the volume covered by the combination of the given cubes.
-fn total_volume(cubes: &[Cube]) -> u64 {
-    let mut x_map: Vec<i64> = cubes.iter()
-        .map(|cube| vec![cube.x1, cube.x2 + 1])
-        .flatten()
-        .collect::<HashSet<i64>>().drain().collect();
-    x_map.sort();
-
-    let mut y_map: Vec<i64> = cubes.iter()
-        .map(|cube| vec![cube.y1, cube.y2 + 1])
-        .flatten()
-        .collect::<HashSet<i64>>().drain().collect();
-    y_map.sort();
-
-    let mut z_map: Vec<i64> = cubes.iter()
-        .map(|cube| vec![cube.z1, cube.z2 + 1])
-        .flatten()
-        .collect::<HashSet<i64>>().drain().collect();
-    z_map.sort();
-
-    let mut covered: Vec<Vec<Vec<bool>>> = Vec::new();
-
-    for _ in x_map.iter() {
-        let mut z_y_plane: Vec<Vec<bool>> = Vec::new();
-        for _ in y_map.iter() {
-            let mut z_line: Vec<bool> = Vec::new();
-            for _ in z_map.iter() {
-                z_line.push(false);
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub struct RebootStep<const N: usize> {
+    pub on: bool,
+    pub cube: Cuboid<N>,
+}
+
+/// Parses a line such as `on x=10..12,y=10..12,z=10..12`, returning the axis names along with the
+/// step. Any names can be used, as long as there are `N` of them.
+fn parse_step<const N: usize>(line: &str) -> Result<(Vec<&str>, RebootStep<N>), String> {
+    let (state, ranges) = line.trim().split_once(' ')
+        .ok_or(format!("Could not parse line: {}", line))?;
+
+    let on = match state {
+        "on" => true,
+        "off" => false,
+        other => return Err(format!("Expected on or off, found {}", other)),
+    };
+
+    let ranges: Vec<&str> = ranges.split(',').collect();
+    if ranges.len() != N {
+        return Err(format!("Expected {} axes, found {}: {}", N, ranges.len(), line));
+    }
+
+    let mut names = Vec::new();
+    let mut cube = Cuboid { ranges: [(0, 0); N] };
+
+    for (axis, range) in ranges.iter().enumerate() {
+        let (name, start, end) = range.split_once('=')
+            .and_then(|(name, range)| {
+                let (start, end) = range.split_once("..")?;
+                Some((name.trim(), start.parse().ok()?, end.parse().ok()?))
+            })
+            .ok_or(format!("Could not parse range \"{}\" in line: {}", range, line))?;
+
+        if start > end {
+            return Err(format!("Empty cuboid: {}", line));
+        }
+        if names.contains(&name) {
+            return Err(format!("Axis {} appears twice in line: {}", name, line));
+        }
+
+        names.push(name);
+        cube.ranges[axis] = (start, end);
+    }
+
+    Ok((names, RebootStep { on, cube }))
+}
+
+/// Parses reboot steps in `N` dimensions. Every line has to use the same axes, in the same order.
+pub fn parse<const N: usize>(input: &str) -> Result<Vec<RebootStep<N>>, String> {
+    let mut axes: Option<Vec<&str>> = None;
+    let mut steps = Vec::new();
+
+    for line in input.lines().filter(|line| !line.trim().is_empty()) {
+        let (names, step) = parse_step(line)?;
+
+        match &axes {
+            Some(axes) if *axes != names => {
+                return Err(format!("Expected axes {:?}, found {:?}", axes, names));
             }
-            z_y_plane.push(z_line);
+            Some(_) => {}
+            None => axes = Some(names),
         }
-        covered.push(z_y_plane);
+
+        steps.push(step);
+    }
+
+    Ok(steps)
+}
+
+/// The state of the reactor, stored as a set of disjoint cuboids that are on.
+///
+/// Applying a step removes every piece that overlaps the step's cuboid and puts back what's left
+/// of it once the cuboid is cut out (at most two smaller pieces per axis). If the step turns
+/// things on, the cuboid itself is then added as a new piece.
+#[derive(Debug, Default)]
+pub struct Reactor<const N: usize> {
+    // Indexed by id, with None for ids that have been freed.
+    pieces: Vec<Option<Cuboid<N>>>,
+    free: Vec<usize>,
+    // The ids of the pieces ordered by their lowest coordinate along the first axis. Since no
+    // piece is wider than `max_width` along that axis, only pieces starting within `max_width`
+    // before a cuboid can overlap it.
+    by_start: BTreeSet<(i64, usize)>,
+    max_width: i64,
+}
+
+impl<const N: usize> Reactor<N> {
+    pub fn new() -> Reactor<N> {
+        Reactor::default()
     }
 
-    for cube in cubes.iter() {
-        for (x_index, x) in x_map.iter().enumerate() {
-            for (y_index, y) in y_map.iter().enumerate() {
-                for (z_index, z) in z_map.iter().enumerate() {
-                    if cube.contains(&Point::new(*x, *y, *z)) {
-                        covered[x_index][y_index][z_index] = true;
-                    }
-                }
+    pub fn from_steps(steps: &[RebootStep<N>]) -> Reactor<N> {
+        let mut reactor = Reactor::new();
+        steps.iter().for_each(|step| reactor.apply(step));
+        reactor
+    }
+
+    fn insert(&mut self, cube: Cuboid<N>) {
+        let id = match self.free.pop() {
+            Some(id) => { self.pieces[id] = Some(cube); id }
+            None => { self.pieces.push(Some(cube)); self.pieces.len() - 1 }
+        };
+
+        let (start, end) = cube.ranges[0];
+        self.by_start.insert((start, id));
+        self.max_width = max(self.max_width, end - start);
+    }
+
+    fn remove(&mut self, id: usize) -> Cuboid<N> {
+        let cube = self.pieces[id].take().unwrap();
+        self.by_start.remove(&(cube.ranges[0].0, id));
+        self.free.push(id);
+        cube
+    }
+
+    fn overlapping(&self, region: &Cuboid<N>) -> Vec<usize> {
+        let (start, end) = region.ranges[0];
+        self.by_start.range((start - self.max_width, 0)..=(end, usize::MAX))
+            .map(|(_, id)| *id)
+            .filter(|id| {
+                Cuboid::intersection(self.pieces[*id].as_ref().unwrap(), region).is_some()
+            })
+            .collect()
+    }
+
+    pub fn apply(&mut self, step: &RebootStep<N>) {
+        for id in self.overlapping(&step.cube) {
+            let piece = self.remove(id);
+            for remainder in piece.subtract(&step.cube) {
+                self.insert(remainder);
             }
         }
+
+        if step.on {
+            self.insert(step.cube);
+        }
+    }
+
+    fn cubes(&self) -> impl Iterator<Item = &Cuboid<N>> {
+        self.pieces.iter().flatten()
+    }
+
+    /// The number of cubes that are on.
+    pub fn volume(&self) -> u128 {
+        self.cubes().map(Cuboid::volume).sum()
     }
 
-    let mut total_area = 0;
+    /// The number of cubes that are on within `region`.
+    pub fn volume_within(&self, region: &Cuboid<N>) -> u128 {
+        self.overlapping(region).iter()
+            .map(|id| Cuboid::intersection(self.pieces[*id].as_ref().unwrap(), region).unwrap())
+            .map(|overlap| overlap.volume())
+            .sum()
+    }
+
+    pub fn is_on(&self, point: &Point<N>) -> bool {
+        self.cubes().any(|cube| cube.contains(point))
+    }
+}
 
-    for (x_index, (x1, x2)) in x_map.iter().zip(x_map.iter().skip(1)).enumerate() {
-        for (y_index, (y1, y2)) in y_map.iter().zip(y_map.iter().skip(1)).enumerate() {
-            for (z_index, (z1, z2)) in z_map.iter().zip(z_map.iter().skip(1)).enumerate() {
-                if covered[x_index][y_index][z_index] {
-                    total_area += ((x2 - x1) * (y2 - y1) * (z2 - z1)) as u64;
-                }
+impl<const N: usize> Reactor<N> {
+    /// The region that's on, as disjoint cuboids. Neighbouring pieces that line up are merged, so
+    /// there are usually fewer cuboids than pieces, though not necessarily as few as possible.
+    pub fn cuboids(&self) -> Vec<Cuboid<N>> {
+        let mut cuboids: Vec<Cuboid<N>> = self.cubes().cloned().collect();
+
+        loop {
+            let count = cuboids.len();
+            for axis in 0..N {
+                cuboids = merge_along(cuboids, axis);
             }
+
+            if cuboids.len() == count { break; }
         }
+
+        cuboids.sort_by_key(|cube| cube.ranges);
+        cuboids
     }
 
-    total_area
+    /// Writes the region that's on as reboot steps (one `on` step per cuboid), using the given
+    /// names for the axes.
+    pub fn to_steps(&self, axes: &[&str; N]) -> String {
+        self.cuboids().iter()
+            .map(|cube| {
+                let ranges: Vec<String> = axes.iter().zip(cube.ranges.iter())
+                    .map(|(name, (start, end))| format!("{}={}..{}", name, start, end))
+                    .collect();
+                format!("on {}\n", ranges.join(","))
+            })
+            .collect()
+    }
 }
 
-pub fn part2(input: &str) -> u64 {
-    let mut cubes: Vec<Cube> = Vec::new();
-    let mut volume_on = 0;
+/// Merges cuboids that touch along `axis` and match exactly along every other axis.
+fn merge_along<const N: usize>(cuboids: Vec<Cuboid<N>>, axis: usize) -> Vec<Cuboid<N>> {
+    let mut groups: HashMap<[Range; N], Vec<Range>> = HashMap::new();
+    for cube in cuboids {
+        let mut key = cube.ranges;
+        key[axis] = (0, 0);
+        groups.entry(key).or_default().push(cube.ranges[axis]);
+    }
+
+    let mut merged = Vec::new();
+    for (key, mut ranges) in groups {
+        ranges.sort();
+
+        let mut current = ranges[0];
+        for range in ranges.into_iter().skip(1) {
+            if range.0 == current.1 + 1 {
+                current.1 = range.1;
+            } else {
+                let mut cube = Cuboid { ranges: key };
+                cube.ranges[axis] = current;
+                merged.push(cube);
+                current = range;
+            }
+        }
+
+        let mut cube = Cuboid { ranges: key };
+        cube.ranges[axis] = current;
+        merged.push(cube);
+    }
+
+    merged
+}
 
-    for line in input.lines().rev() {
-        let (c, x1, x2, y1, y2, z1, z2) =
-            scanf!(line, "{} x={}..{},y={}..{},z={}..{}",
-                String, i64, i64, i64, i64, i64, i64).unwrap();
+// Slices bigger than this along either axis are too big to be worth printing.
+const MAX_SLICE_SIZE: i64 = 200;
 
-        let new_cube = Cube::new(x1, x2, y1, y2, z1, z2);
+impl Reactor<3> {
+    /// Draws the plane at `z`, with x going across and y going down, covering just the part of
+    /// the plane that has anything on.
+    pub fn render_slice(&self, z: i64) -> Result<String, String> {
+        let plane: Vec<&Cube> = self.cubes()
+            .filter(|cube| cube.ranges[2].0 <= z && z <= cube.ranges[2].1)
+            .collect();
 
-        if cubes.iter().any(|cube| cube.contains_cube(&new_cube)) {
-            // The cube is entirely contained with a previous cube, so we can just ignore it.
-            continue;
+        if plane.is_empty() {
+            return Ok(String::new());
         }
 
-        if c == "on" {
-            let mut overlaps = Vec::new();
+        let x1 = plane.iter().map(|cube| cube.ranges[0].0).min().unwrap();
+        let x2 = plane.iter().map(|cube| cube.ranges[0].1).max().unwrap();
+        let y1 = plane.iter().map(|cube| cube.ranges[1].0).min().unwrap();
+        let y2 = plane.iter().map(|cube| cube.ranges[1].1).max().unwrap();
 
-            for cube in cubes.iter() {
-                if let Some(overlap) = Cube::intersection(cube, &new_cube) {
-                    overlaps.push(overlap);
-                }
-            }
+        if x2 - x1 >= MAX_SLICE_SIZE || y2 - y1 >= MAX_SLICE_SIZE {
+            return Err(format!(
+                "The slice at z={} covers x={}..{},y={}..{}, which is too big to draw",
+                z, x1, x2, y1, y2));
+        }
 
-            volume_on += new_cube.volume() - total_volume(&overlaps);
+        let mut output = format!("z={}, x={}..{}, y={}..{}\n", z, x1, x2, y1, y2);
+        for y in y1..=y2 {
+            for x in x1..=x2 {
+                let on = plane.iter().any(|cube| cube.contains(&[x, y, z]));
+                output.push(if on { '#' } else { '.' });
+            }
+            output.push('\n');
         }
 
-        cubes.push(new_cube);
+        Ok(output)
     }
+}
 
-    volume_on
+pub fn part1(input: &str) -> u128 {
+    let reactor = Reactor::from_steps(&parse::<3>(input).unwrap());
+    reactor.volume_within(&Cube::new(-50, 50, -50, 50, -50, 50))
+}
+
+pub fn part2(input: &str) -> u128 {
+    Reactor::from_steps(&parse::<3>(input).unwrap()).volume()
 }
 
 #[cfg(test)]
@@ -328,19 +485,176 @@ off x=-22..28,y=0..0,z=0..0";
     }
 
 
+    fn all_on(cubes: &[Cube]) -> Reactor<3> {
+        let steps: Vec<RebootStep<3>> = cubes.iter()
+            .map(|cube| RebootStep { on: true, cube: *cube })
+            .collect();
+        Reactor::from_steps(&steps)
+    }
+
     #[test]
     fn test_total_area() {
         let cube1 = Cube::new(0, 9, 0, 9, 0, 0);
         let cube2 = Cube::new(0, 5, 0, 5, 0, 0);
-        assert_eq!(100, total_volume(&vec![cube1, cube2]));
+        assert_eq!(100, all_on(&[cube1, cube2]).volume());
 
         let cube1 = Cube::new(5, 9, 5, 9, 0, 0);
         let cube2 = Cube::new(1, 5, 1, 5, 0, 0);
-        assert_eq!(49, total_volume(&vec![cube1, cube2]));
+        assert_eq!(49, all_on(&[cube1, cube2]).volume());
 
         let cube1 = Cube::new(1, 5, 3, 4, 0, 0);
         let cube2 = Cube::new(2, 3, 2, 3, 0, 0);
         let cube3 = Cube::new(3, 4, 1, 3, 0, 0);
-        assert_eq!(15, total_volume(&vec![cube1, cube2, cube3]));
+        assert_eq!(15, all_on(&[cube1, cube2, cube3]).volume());
+    }
+
+    #[test]
+    fn test_reactor() {
+        let reactor = Reactor::from_steps(&parse::<3>("\
+on x=10..12,y=10..12,z=10..12
+on x=11..13,y=11..13,z=11..13
+off x=9..11,y=9..11,z=9..11
+on x=10..10,y=10..10,z=10..10").unwrap());
+
+        // The steps from the puzzle description.
+        assert_eq!(39, reactor.volume());
+        assert!(reactor.is_on(&[10, 10, 10]));
+        assert!(!reactor.is_on(&[11, 11, 11]));
+        assert!(reactor.is_on(&[13, 13, 13]));
+        assert!(!reactor.is_on(&[14, 13, 13]));
+
+        assert_eq!(1, reactor.volume_within(&Cube::new(10, 11, 10, 11, 10, 11)));
+        assert_eq!(0, reactor.volume_within(&Cube::new(20, 30, 20, 30, 20, 30)));
+    }
+
+    #[test]
+    fn test_subtract() {
+        let cube = Cube::new(0, 9, 0, 9, 0, 9);
+
+        assert_eq!(vec![cube], cube.subtract(&Cube::new(20, 30, 0, 9, 0, 9)));
+        assert!(cube.subtract(&Cube::new(-5, 15, -5, 15, -5, 15)).is_empty());
+
+        // A hole in the middle leaves six pieces.
+        let pieces = cube.subtract(&Cube::new(4, 5, 4, 5, 4, 5));
+        assert_eq!(6, pieces.len());
+        assert_eq!(1000 - 8, pieces.iter().map(Cube::volume).sum::<u128>());
+
+        for (i, a) in pieces.iter().enumerate() {
+            for b in pieces.iter().skip(i + 1) {
+                assert_eq!(None, Cube::intersection(a, b));
+            }
+        }
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(parse::<3>("on x=1..2,y=1..2").is_err());
+        assert!(parse::<3>("toggle x=1..2,y=1..2,z=1..2").is_err());
+        assert!(parse::<3>("on x=2..1,y=1..2,z=1..2").is_err());
+        assert!(parse::<3>("on x=1..2,y=1..2,z=1..a").is_err());
+        assert!(parse::<3>("on x=1..2,x=1..2,z=1..2").is_err());
+        assert!(parse::<3>("on x=1..2,y=1..2,z=1..2\noff x=1..2,z=1..2,y=1..2").is_err());
+    }
+
+    #[test]
+    fn test_parse_named_axes() {
+        let steps = parse::<4>("on w=1..2,x=3..4,y=5..6,z=7..8\noff w=0..0,x=0..0,y=0..0,z=0..0");
+        assert_eq!(Ok(vec![
+            RebootStep { on: true, cube: Cuboid::from_ranges([(1, 2), (3, 4), (5, 6), (7, 8)]) },
+            RebootStep { on: false, cube: Cuboid::from_ranges([(0, 0); 4]) },
+        ]), steps);
+
+        assert!(parse::<2>("on x=1..2,y=1..2,z=1..2").is_err());
+    }
+
+    #[test]
+    fn test_contains_cuboid() {
+        let cube = Cube::new(0, 9, 0, 9, 0, 9);
+        assert!(cube.contains_cuboid(&Cube::new(0, 9, 2, 3, 4, 5)));
+        assert!(!cube.contains_cuboid(&Cube::new(0, 10, 2, 3, 4, 5)));
+        assert!(!cube.contains_cuboid(&Cube::new(20, 30, 20, 30, 20, 30)));
+    }
+
+    #[test]
+    fn test_2d_reactor() {
+        // Two overlapping squares, with a hole punched in the middle of their overlap.
+        let reactor = Reactor::from_steps(&parse::<2>("\
+on x=0..9,y=0..9
+on x=5..14,y=5..14
+off x=7..7,y=7..7").unwrap());
+
+        assert_eq!(100 + 100 - 25 - 1, reactor.volume());
+        assert!(!reactor.is_on(&[7, 7]));
+        assert!(reactor.is_on(&[14, 14]));
+        assert_eq!(24, reactor.volume_within(&Cuboid::from_ranges([(5, 9), (5, 9)])));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_4d_reactor() {
+        let reactor = Reactor::from_steps(&parse::<4>("\
+on w=0..9,x=0..9,y=0..9,z=0..9
+off w=0..4,x=0..9,y=0..9,z=0..9
+on w=-5..0,x=0..0,y=0..0,z=0..0").unwrap());
+
+        assert_eq!(5000 + 6, reactor.volume());
+        assert!(reactor.is_on(&[-5, 0, 0, 0]));
+        assert!(!reactor.is_on(&[1, 0, 0, 0]));
+
+        let hypercube = Cuboid::from_ranges([(0, 9); 4]);
+        let pieces = hypercube.subtract(&Cuboid::from_ranges([(4, 5); 4]));
+        assert_eq!(8, pieces.len());
+        assert_eq!(10_000 - 16, pieces.iter().map(Cuboid::volume).sum::<u128>());
+    }
+
+    #[test]
+    fn test_cuboids() {
+        // The first step is split into pieces by the second, but they merge back together.
+        let reactor = Reactor::from_steps(&parse::<3>("\
+on x=0..9,y=0..9,z=0..0
+on x=3..5,y=3..5,z=0..0
+off x=20..30,y=0..9,z=0..0").unwrap());
+        assert_eq!(vec![Cube::new(0, 9, 0, 9, 0, 0)], reactor.cuboids());
+
+        let reactor = Reactor::from_steps(&parse::<3>(INPUT).unwrap());
+        let cuboids = reactor.cuboids();
+        assert!(cuboids.len() <= reactor.cubes().count());
+
+        for (i, a) in cuboids.iter().enumerate() {
+            for b in cuboids.iter().skip(i + 1) {
+                assert_eq!(None, Cube::intersection(a, b));
+            }
+        }
+    }
+
+    #[test]
+    fn test_to_steps_round_trip() {
+        let reactor = Reactor::from_steps(&parse::<3>(INPUT).unwrap());
+        let exported = reactor.to_steps(&["x", "y", "z"]);
+        assert!(exported.lines().all(|line| line.starts_with("on x=")));
+
+        let reimported = Reactor::from_steps(&parse::<3>(&exported).unwrap());
+        assert_eq!(reactor.volume(), reimported.volume());
+        assert_eq!(reactor.cuboids(), reimported.cuboids());
+    }
+
+    #[test]
+    fn test_render_slice() {
+        let reactor = Reactor::from_steps(&parse::<3>("\
+on x=10..12,y=10..12,z=10..12
+on x=11..13,y=11..13,z=11..13
+off x=9..11,y=9..11,z=9..11
+on x=10..10,y=10..10,z=10..10").unwrap());
+
+        assert_eq!(Ok(String::from("\
+z=11, x=10..13, y=10..13
+..#.
+..##
+####
+.###
+")), reactor.render_slice(11));
+        assert_eq!(Ok(String::new()), reactor.render_slice(20));
+
+        let reactor = Reactor::from_steps(&parse::<3>(INPUT).unwrap());
+        assert!(reactor.render_slice(0).is_err());
+    }
+}
@@ -1,48 +1,43 @@
 use std::cmp::{Ordering};
 use std::collections::{BinaryHeap, HashMap};
 use std::fmt::{Debug, Formatter, Write};
-use std::hash::{Hash, Hasher};
+use std::hash::Hash;
 
+/// The type of an amphipod, which is also the index of the room it belongs in: 0 for Amber, 1 for
+/// Bronze and so on.
 #[derive(PartialEq, Eq, Copy, Clone, Hash)]
-enum Type {
-    A, B, C, D,
-}
+pub struct Type(pub u8);
 
 impl Type {
-    fn room_x(&self) -> u8 {
-        match self {
-            Type::A => 2,
-            Type::B => 4,
-            Type::C => 6,
-            Type::D => 8,
+    pub const A: Type = Type(0);
+    pub const B: Type = Type(1);
+    pub const C: Type = Type(2);
+    pub const D: Type = Type(3);
+
+    fn from_letter(c: char) -> Option<Type> {
+        if c.is_ascii_uppercase() {
+            Some(Type(c as u8 - b'A'))
+        } else {
+            None
         }
     }
 
-    fn cost(&self) -> u32 {
-        match self {
-            Type::A => 1,
-            Type::B => 10,
-            Type::C => 100,
-            Type::D => 1000,
-        }
+    fn letter(&self) -> char {
+        (b'A' + self.0) as char
     }
 }
 
 impl Debug for Type {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        match self {
-            Type::A => f.write_char('A'),
-            Type::B => f.write_char('B'),
-            Type::C => f.write_char('C'),
-            Type::D => f.write_char('D'),
-        }
+        f.write_char(self.letter())
     }
 }
 
+/// A position in the burrow: y = 0 is the hallway and rooms go downwards from y = 1.
 #[derive(Debug, PartialEq, Eq, Ord, PartialOrd, Hash, Copy, Clone)]
-struct Pos {
-    x: u8,
-    y: u8,
+pub struct Pos {
+    pub x: u8,
+    pub y: u8,
 }
 
 impl Pos {
@@ -51,93 +46,211 @@ impl Pos {
     }
 }
 
-#[derive(Clone, PartialEq, Eq)]
-struct State {
-    /// TODO: Try this with a simpler data structure.
-    positions: HashMap<Pos, Type>,
+/// Costs go up by a factor of ten for each type, so more would overflow the total energy.
+const MAX_ROOMS: usize = 6;
+const MAX_SLOTS: usize = 48;
+
+/// The layout of the burrow: how long the hallway is and where the rooms are. The rooms belong to
+/// the types in order from left to right.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Burrow {
+    hallway_length: u8,
+    room_size: u8,
+    /// The x position of each type's room.
+    rooms: Vec<u8>,
+    /// The index of the room at each x position in the hallway, if there is one.
+    room_at: Vec<Option<u8>>,
+    /// Where pods can stop in the hallway, which is anywhere not directly outside a room.
+    stops: Vec<u8>,
+    costs: Vec<u32>,
 }
 
-impl Hash for State {
-    fn hash<H: Hasher>(&self, state: &mut H) {
-        self.positions.iter().for_each(|(pos, t)| {
-            pos.hash(state);
-            t.hash(state);
-        })
+impl Burrow {
+    pub fn new(hallway_length: u8, rooms: Vec<u8>, room_size: u8) -> Result<Burrow, String> {
+        if rooms.is_empty() || rooms.len() > MAX_ROOMS {
+            return Err(format!("There must be between 1 and {} rooms, found {}",
+                               MAX_ROOMS, rooms.len()));
+        }
+        if room_size == 0 {
+            return Err(String::from("Rooms must have space for at least one amphipod"));
+        }
+        if let Some(x) = rooms.iter().find(|x| **x >= hallway_length) {
+            return Err(format!("The room at {} is past the end of the hallway", x));
+        }
+        if !rooms.windows(2).all(|pair| pair[0] < pair[1]) {
+            return Err(String::from("Rooms must be listed from left to right"));
+        }
+
+        let slots = hallway_length as usize + rooms.len() * room_size as usize;
+        if slots > MAX_SLOTS {
+            return Err(format!("The burrow has {} spaces, but at most {} are supported",
+                               slots, MAX_SLOTS));
+        }
+
+        let mut room_at = vec![None; hallway_length as usize];
+        for (i, x) in rooms.iter().enumerate() {
+            room_at[*x as usize] = Some(i as u8);
+        }
+        let stops = (0..hallway_length).filter(|x| room_at[*x as usize].is_none()).collect();
+        let costs = (0..rooms.len()).map(|i| 10u32.pow(i as u32)).collect();
+
+        Ok(Burrow { hallway_length, room_size, rooms, room_at, stops, costs })
     }
-}
 
-impl Debug for State {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        f.write_str("#############\n")?;
-        for y in 0..3 {
-            f.write_char('#')?;
-            for x in 0..11 {
-                if let Some(t) = self.positions.get(&Pos::new(x, y)) {
-                    t.fmt(f)?;
-                } else {
-                    f.write_char(' ')?;
+    /// The burrow from the puzzle: four rooms off an eleven space hallway.
+    #[cfg(test)]
+    fn standard(room_size: u8) -> Burrow {
+        Burrow::new(11, vec![2, 4, 6, 8], room_size).unwrap()
+    }
+
+    pub fn room_size(&self) -> u8 {
+        self.room_size
+    }
+
+    pub fn room_count(&self) -> usize {
+        self.rooms.len()
+    }
+
+    fn room_x(&self, t: Type) -> u8 {
+        self.rooms[t.0 as usize]
+    }
+
+    fn cost(&self, t: Type) -> u32 {
+        self.costs[t.0 as usize]
+    }
+
+    fn is_room(&self, x: u8) -> bool {
+        self.room_at.get(x as usize).is_some_and(|room| room.is_some())
+    }
+
+    /// The index of the position in `State::slots`: the hallway comes first, followed by each room
+    /// from top to bottom.
+    fn index(&self, pos: &Pos) -> usize {
+        if pos.y == 0 {
+            pos.x as usize
+        } else {
+            let room = self.room_at[pos.x as usize].unwrap() as usize;
+            self.hallway_length as usize + room * self.room_size as usize + pos.y as usize - 1
+        }
+    }
+
+    fn pos(&self, index: usize) -> Pos {
+        let hallway_length = self.hallway_length as usize;
+        if index < hallway_length {
+            Pos::new(index as u8, 0)
+        } else {
+            let room = (index - hallway_length) / self.room_size as usize;
+            let y = (index - hallway_length) % self.room_size as usize + 1;
+            Pos::new(self.rooms[room], y as u8)
+        }
+    }
+
+    /// Draws the burrow in the same format as the puzzle input.
+    pub fn draw(&self, state: &State) -> String {
+        let width = self.hallway_length as usize + 2;
+        // The columns of the walls around the lower parts of the rooms.
+        let left = *self.rooms.first().unwrap() as usize;
+        let right = *self.rooms.last().unwrap() as usize + 2;
+
+        let mut output = "#".repeat(width);
+        output.push('\n');
+        for y in 0..=self.room_size {
+            for column in 0..width {
+                let x = column as u8;
+                if column > 0 && (y == 0 || self.is_room(x - 1)) && column < width - 1 {
+                    output.push(state.get(self, &Pos::new(x - 1, y)).map_or('.', |t| t.letter()));
+                } else if y <= 1 || (left..=right).contains(&column) {
+                    output.push('#');
+                } else if column < left {
+                    output.push(' ');
                 }
             }
-            f.write_str("#\n")?;
+            output.push('\n');
         }
-        f.write_str("#############\n")
+        output.push_str(&" ".repeat(left));
+        output.push_str(&"#".repeat(right - left + 1));
+        output
     }
 }
 
+/// The contents of every hallway and room slot, so states are small, cheap to copy and hash. How
+/// slots map to positions depends on the `Burrow`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct State {
+    slots: [u8; MAX_SLOTS],
+}
+
+const EMPTY: u8 = u8::MAX;
+
 fn abs_diff(a: u8, b: u8) -> u8 {
     let min = u8::min(a, b);
     let max = u8::max(a, b);
     max - min
 }
 
-// TODO: Turn into a map?
-const ROOM_A: u8 = 2;
-const ROOM_B: u8 = 4;
-const ROOM_C: u8 = 6;
-const ROOM_D: u8 = 8;
-
 impl State {
+    fn from_positions<I: IntoIterator<Item = (Pos, Type)>>(burrow: &Burrow, positions: I)
+        -> State {
+        let mut slots = [EMPTY; MAX_SLOTS];
+        for (pos, t) in positions {
+            slots[burrow.index(&pos)] = t.0;
+        }
+        State { slots }
+    }
+
+    #[cfg(test)]
+    #[allow(clippy::too_many_arguments)]
     fn new(a1: Type, a2: Type, b1: Type, b2: Type,
             c1: Type, c2: Type, d1: Type, d2: Type) -> State {
-        let mut positions = HashMap::new();
-
-        positions.insert(Pos::new(ROOM_A, 1), a1);
-        positions.insert(Pos::new(ROOM_A, 2), a2);
-        positions.insert(Pos::new(ROOM_B, 1), b1);
-        positions.insert(Pos::new(ROOM_B, 2), b2);
-        positions.insert(Pos::new(ROOM_C, 1), c1);
-        positions.insert(Pos::new(ROOM_C, 2), c2);
-        positions.insert(Pos::new(ROOM_D, 1), d1);
-        positions.insert(Pos::new(ROOM_D, 2), d2);
+        State::from_positions(&Burrow::standard(2), [
+            (Pos::new(2, 1), a1),
+            (Pos::new(2, 2), a2),
+            (Pos::new(4, 1), b1),
+            (Pos::new(4, 2), b2),
+            (Pos::new(6, 1), c1),
+            (Pos::new(6, 2), c2),
+            (Pos::new(8, 1), d1),
+            (Pos::new(8, 2), d2),
+        ])
+    }
 
-        State { positions }
+    fn get(&self, burrow: &Burrow, pos: &Pos) -> Option<Type> {
+        match self.slots[burrow.index(pos)] {
+            EMPTY => None,
+            t => Some(Type(t)),
+        }
     }
 
-    fn new_state(&self, old: &Pos, new: &Pos) -> State {
-        let mut new_positions = self.positions.clone();
+    fn is_occupied(&self, burrow: &Burrow, pos: &Pos) -> bool {
+        self.slots[burrow.index(pos)] != EMPTY
+    }
 
-        if old != new {
-            let t = new_positions.remove(old).unwrap();
-            new_positions.insert(*new, t);
-        }
+    /// Returns the position and type of every amphipod.
+    fn pods<'a>(&'a self, burrow: &'a Burrow) -> impl Iterator<Item = (Pos, Type)> + 'a {
+        self.slots.iter()
+            .enumerate()
+            .filter(|(_, slot)| **slot != EMPTY)
+            .map(move |(i, slot)| (burrow.pos(i), Type(*slot)))
+    }
 
-        State {
-            positions: new_positions
-        }
+    fn new_state(&self, burrow: &Burrow, old: &Pos, new: &Pos) -> State {
+        let mut slots = self.slots;
+        slots.swap(burrow.index(old), burrow.index(new));
+        State { slots }
     }
 
     /// Is the amphipod at its destination?
-    fn at_destination(&self, pos: &Pos, room_size: u8) -> bool {
+    fn at_destination(&self, burrow: &Burrow, pos: &Pos) -> bool {
         if pos.y == 0 { return false; }
 
-        if let Some(pod) = self.positions.get(pos) {
-            if pos.x != pod.room_x() {
+        if let Some(pod) = self.get(burrow, pos) {
+            if pos.x != burrow.room_x(pod) {
                 return false;
             }
 
             // Ensure this cell and all those below are filled with the right type of pod.
-            for y in pos.y..(room_size + 1) {
-                if self.positions.get(&Pos::new(pos.x, y)) != Some(pod) {
+            for y in pos.y..(burrow.room_size + 1) {
+                if self.get(burrow, &Pos::new(pos.x, y)) != Some(pod) {
                     return false;
                 }
             }
@@ -148,21 +261,21 @@ impl State {
         false
     }
 
-    fn is_finished(&self) -> bool {
-        self.positions.iter().all(|(pos, t)| pos.x == t.room_x())
+    fn is_finished(&self, burrow: &Burrow) -> bool {
+        self.pods(burrow).all(|(pos, t)| pos.x == burrow.room_x(t))
     }
 
     /// Checks whether a pod can move into that room. It will return None if the room contains a
     /// pod that should not end up there. If the room can be moved into, it returns the y value
     /// the pod will end up at.
-    fn room_open(&self, t: &Type, room_size: u8) -> Option<u8> {
-        for i in 0..room_size {
-            let y = room_size - i;
-            let pos = Pos::new(t.room_x(), y);
+    fn room_open(&self, burrow: &Burrow, t: &Type) -> Option<u8> {
+        for i in 0..burrow.room_size {
+            let y = burrow.room_size - i;
+            let pos = Pos::new(burrow.room_x(*t), y);
 
             // Find the lowest empty room.
-            if let Some(pod) = self.positions.get(&pos) {
-                if pod != t {
+            if let Some(pod) = self.get(burrow, &pos) {
+                if pod != *t {
                     // The cell is occupied by a pod of the wrong type. We can't move into this
                     // room at all.
                     return None;
@@ -178,9 +291,9 @@ impl State {
     }
 
     /// Returns whether the pod at given coordinates can leave the room.
-    fn can_leave(&self, pos: &Pos) -> bool {
+    fn can_leave(&self, burrow: &Burrow, pos: &Pos) -> bool {
         for y in 0..pos.y {
-            if self.positions.contains_key(&Pos::new(pos.x, y)) {
+            if self.is_occupied(burrow, &Pos::new(pos.x, y)) {
                 return false;
             }
         }
@@ -188,61 +301,63 @@ impl State {
     }
 
     /// Returns possible states resulting from the pod at the given position moving.
-    fn possible_moves(&self, pos: &Pos, room_size: u8) -> Vec<(u32, State)> {
-        let mut possible_states: Vec<(u32, State)> = Vec::new();
+    fn possible_moves(&self, burrow: &Burrow, pos: &Pos) -> Vec<(Move, State)> {
+        let mut possible_states: Vec<(Move, State)> = Vec::new();
 
-        if self.at_destination(pos, room_size) { return possible_states; }
+        if self.at_destination(burrow, pos) { return possible_states; }
 
-        let pod = self.positions.get(pos).unwrap();
+        let pod = self.get(burrow, pos).unwrap();
+        let room_x = burrow.room_x(pod);
 
         if pos.y == 0 {
             // The pod can only move into its destination.
-            if let Some(y) = self.room_open(pod, room_size) {
+            if let Some(y) = self.room_open(burrow, &pod) {
                 // Check the path between pos.x and room.x is clear.
-                if let Some(x_dist) = self.clear_path(pos.x, pod.room_x()) {
-                    let dest = Pos::new(pod.room_x(), y);
+                if let Some(x_dist) = self.clear_path(burrow, pos.x, room_x) {
+                    let dest = Pos::new(room_x, y);
 
-                    let cost = ((y + x_dist) as u32) * pod.cost();
+                    let cost = ((y + x_dist) as u32) * burrow.cost(pod);
 
-                    possible_states.push((cost, self.new_state(pos, &dest)));
+                    let step = Move { pod, from: *pos, to: dest, cost };
+                    possible_states.push((step, self.new_state(burrow, pos, &dest)));
                 }
             }
-        } else if self.can_leave(pos) {
-            for x in [0, 1, 3, 5, 7, 9, 10] {
+        } else if self.can_leave(burrow, pos) {
+            for &x in &burrow.stops {
                 // Check nothing is already occupying that spot.
-                if self.positions.contains_key(&Pos::new(x, 0)) { continue; }
+                if self.is_occupied(burrow, &Pos::new(x, 0)) { continue; }
 
                 // Check there's a clear path to that spot.
-                if let Some(x_dist) = self.clear_path(pos.x, x) {
+                if let Some(x_dist) = self.clear_path(burrow, pos.x, x) {
                     let dest = Pos::new(x, 0);
-                    let cost = ((pos.y + x_dist) as u32) * pod.cost();
+                    let cost = ((pos.y + x_dist) as u32) * burrow.cost(pod);
 
-                    possible_states.push((cost, self.new_state(pos, &dest)));
+                    let step = Move { pod, from: *pos, to: dest, cost };
+                    possible_states.push((step, self.new_state(burrow, pos, &dest)));
                 }
             }
         }
         possible_states
     }
 
-    fn all_possible_moves(&self, room_size: u8) -> Vec<(u32, State)> {
-        self.positions.iter()
-            .map(|(pos, _)| self.possible_moves(pos, room_size))
-            .flatten()
+    fn all_possible_moves(&self, burrow: &Burrow) -> Vec<(Move, State)> {
+        self.pods(burrow)
+            .flat_map(|(pos, _)| self.possible_moves(burrow, &pos))
             .collect()
     }
 
     /// Checks if there is a clear path along the top corridor between the two
     /// x positions. Does not check the end and start position. If there is, it returns the
     /// distance.
-    fn clear_path(&self, x1: u8, x2: u8) -> Option<u8> {
+    fn clear_path(&self, burrow: &Burrow, x1: u8, x2: u8) -> Option<u8> {
         // TODO: I'm doing a lot of duplicate work building the possible paths.
 
         let x_min = u8::min(x1, x2);
         let x_max = u8::max(x1, x2);
 
-        // TODO: Don't bother checking positions 2, 4, 6, 8.
+        // TODO: Don't bother checking the spaces outside rooms.
         for x in (x_min + 1)..x_max {
-            if self.positions.contains_key(&Pos::new(x, 0)) {
+            if self.is_occupied(burrow, &Pos::new(x, 0)) {
                 return None;
             }
         }
@@ -250,17 +365,31 @@ impl State {
     }
 
     /// Gives a lower bound on the cost between the current state and the finished state.
-    fn distance_estimate(&self) -> u32 {
-        self.positions.iter()
+    ///
+    /// Every pod not yet at its destination has to climb out of the room it's in, walk along the
+    /// hallway to its own room (stepping out and back if it's already there but blocking another
+    /// pod) and then go down into one of the free spaces. The pods going into a room each need a
+    /// different space, so between them they need at least 1 + 2 + ... + k steps down.
+    fn distance_estimate(&self, burrow: &Burrow) -> u32 {
+        let mut entering = vec![0; burrow.room_count()];
+
+        let moving: u32 = self.pods(burrow)
+            .filter(|(pos, _)| !self.at_destination(burrow, pos))
             .map(|(pos, t)| {
-                // abs_diff(pos.x, t.room_x()) as u32 * t.cost()
-                if pos.x == t.room_x() {
-                    0
-                } else {
-                    (abs_diff(pos.x, t.room_x()) + pos.y + 1) as u32 * t.cost()
-                }
+                entering[t.0 as usize] += 1;
+
+                let room_x = burrow.room_x(t);
+                let across = if pos.x == room_x { 2 } else { abs_diff(pos.x, room_x) };
+                (pos.y + across) as u32 * burrow.cost(t)
             })
-            .sum()
+            .sum();
+
+        let filling: u32 = entering.iter()
+            .enumerate()
+            .map(|(t, k)| k * (k + 1) / 2 * burrow.cost(Type(t as u8)))
+            .sum();
+
+        moving + filling
     }
 }
 
@@ -283,136 +412,256 @@ impl Ord for SearchState {
     }
 }
 
-fn search(state: &State, room_size: u8) {
+/// Whether to guide the search with `State::distance_estimate` (A*) or not (Dijkstra's algorithm).
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum Strategy {
+    Dijkstra,
+    AStar,
+}
+
+/// How much work the search did.
+#[derive(Debug, Default, PartialEq, Eq, Copy, Clone)]
+pub struct SearchStats {
+    /// States taken off the heap and expanded.
+    pub expanded: usize,
+    pub max_heap_size: usize,
+    /// States taken off the heap that had already been reached more cheaply.
+    pub duplicates_skipped: usize,
+}
+
+/// A single amphipod moving, either out into the hallway or into its room.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub struct Move {
+    pub pod: Type,
+    pub from: Pos,
+    pub to: Pos,
+    pub cost: u32,
+}
+
+#[derive(Debug)]
+pub struct Solution {
+    pub burrow: Burrow,
+    pub start: State,
+    pub energy: u32,
+    pub moves: Vec<Move>,
+}
+
+impl Solution {
+    /// Draws the burrow before the first move and after every move.
+    pub fn render(&self) -> String {
+        let mut state = self.start.clone();
+        let mut output = format!("{}\n", self.burrow.draw(&state));
+        let mut energy = 0;
+
+        for step in &self.moves {
+            state = state.new_state(&self.burrow, &step.from, &step.to);
+            energy += step.cost;
+
+            output.push_str(&format!("\n{:?} ({}, {}) -> ({}, {}) for {}, total {}\n",
+                                     step.pod, step.from.x, step.from.y, step.to.x, step.to.y,
+                                     step.cost, energy));
+            output.push_str(&format!("{}\n", self.burrow.draw(&state)));
+        }
+
+        output
+    }
+}
+
+fn search(burrow: &Burrow, state: &State, strategy: Strategy) -> (Option<Solution>, SearchStats) {
+    let estimate = |state: &State| match strategy {
+        Strategy::Dijkstra => 0,
+        Strategy::AStar => state.distance_estimate(burrow),
+    };
+
+    let mut stats = SearchStats::default();
     let mut costs: HashMap<State, u32> = HashMap::new();
+    // The state each state was best reached from, and the move taken to get there.
+    let mut previous: HashMap<State, (State, Move)> = HashMap::new();
     let mut heap = BinaryHeap::new();
 
     costs.insert(state.clone(), 0);
     heap.push(SearchState {
         cost: 0,
         state: state.clone(),
-        estimate: state.distance_estimate(),
+        estimate: estimate(state),
     });
 
-    let mut iter = 0;
-
-    while let Some(SearchState { cost, state, .. }) = heap.pop() {
-        iter += 1;
-        // println!("Considering, cost: {}", cost);
-        // println!("{:?}", state);
-        if state.is_finished() {
-            println!("Found our goal: {}, {}", cost, iter);
-            return;
+    while let Some(SearchState { cost, state: current, .. }) = heap.pop() {
+        if current.is_finished(burrow) {
+            let mut moves = Vec::new();
+            let mut at = &current;
+            while let Some((before, step)) = previous.get(at) {
+                moves.push(*step);
+                at = before;
+            }
+            moves.reverse();
+
+            let solution = Solution {
+                burrow: burrow.clone(),
+                start: state.clone(),
+                energy: cost,
+                moves,
+            };
+            return (Some(solution), stats);
         }
 
         // We've already found a better way.
-        if cost > *costs.get(&state).unwrap_or(&u32::MAX) { continue; }
+        if cost > *costs.get(&current).unwrap_or(&u32::MAX) {
+            stats.duplicates_skipped += 1;
+            continue;
+        }
+        stats.expanded += 1;
 
         // For each new state we can get to...
-        for (travel_cost, new_state) in state.all_possible_moves(room_size) {
-            let new_cost = cost + travel_cost;
-            // let tentative_cost = cost + travel_cost;
-            // let new_cost = cost + travel_cost + new_state.distance_estimate();
+        for (step, new_state) in current.all_possible_moves(burrow) {
+            let new_cost = cost + step.cost;
 
             // If we've found a better cost to a destination, add it
             // (or if we've found a new destination).
             if new_cost < *costs.get(&new_state).unwrap_or(&u32::MAX) {
                 costs.insert(new_state.clone(), new_cost);
+                previous.insert(new_state.clone(), (current.clone(), step));
 
                 let next = SearchState {
                     cost: new_cost,
-                    estimate: new_state.distance_estimate(),
+                    estimate: estimate(&new_state),
                     state: new_state,
                 };
 
                 heap.push(next);
+                stats.max_heap_size = stats.max_heap_size.max(heap.len());
+            }
+        }
+    }
+
+    (None, stats)
+}
+
+/// Parses a diagram of the burrow, working out its layout from the walls.
+///
+/// #############
+/// #...........#
+/// ###B#C#B#D###
+///   #A#D#C#A#
+///   #########
+fn parse(input: &str) -> Result<(Burrow, State), String> {
+    let lines: Vec<&str> = input.lines().filter(|line| !line.trim().is_empty()).collect();
+
+    if lines.len() < 4 {
+        return Err(String::from("The diagram needs a hallway and at least one row of rooms"));
+    }
+
+    // The first line is the top wall and the last is the bottom one, leaving the hallway (y = 0)
+    // and then the rows of rooms. Inside the walls, x = 0 is the second character on each line.
+    let hallway_length = lines[1].trim_end().len().saturating_sub(2);
+    if hallway_length == 0 || hallway_length > MAX_SLOTS {
+        return Err(format!("Unexpected hallway length {}", hallway_length));
+    }
+    let room_size = lines.len() - 3;
+    if room_size > MAX_SLOTS {
+        return Err(format!("Rooms can be at most {} deep", MAX_SLOTS));
+    }
+
+    let mut rooms: Option<Vec<u8>> = None;
+    let mut positions = Vec::new();
+
+    for (y, line) in lines[1..(lines.len() - 1)].iter().enumerate() {
+        let mut spaces = Vec::new();
+
+        for (i, c) in line.chars().enumerate().skip(1) {
+            let pos = Pos::new((i - 1) as u8, y as u8);
+
+            match c {
+                '#' | ' ' => continue,
+                '.' => {}
+                c => match Type::from_letter(c) {
+                    Some(t) => positions.push((pos, t)),
+                    None => return Err(format!("Unexpected character '{}' on line {}", c, y + 2)),
+                },
             }
+
+            if pos.x as usize >= hallway_length {
+                return Err(format!("({}, {}) is past the end of the hallway", pos.x, pos.y));
+            }
+            spaces.push(pos.x);
+        }
+
+        if y == 0 {
+            if spaces.len() != hallway_length {
+                return Err(String::from("The hallway has a wall in it"));
+            }
+        } else if let Some(rooms) = &rooms {
+            if *rooms != spaces {
+                return Err(format!("The rooms on line {} don't line up with those above", y + 2));
+            }
+        } else {
+            rooms = Some(spaces);
+        }
+    }
+
+    let burrow = Burrow::new(hallway_length as u8, rooms.unwrap(), room_size as u8)?;
+
+    for (pos, t) in &positions {
+        if t.0 as usize >= burrow.room_count() {
+            return Err(format!("There's no room for amphipod {:?}", t));
+        }
+        if pos.y == 0 && burrow.is_room(pos.x) {
+            return Err(format!("Amphipod {:?} is blocking the entrance to a room", t));
+        }
+    }
+    for t in (0..burrow.room_count()).map(|i| Type(i as u8)) {
+        let count = positions.iter().filter(|(_, pod)| *pod == t).count();
+        if count != room_size {
+            return Err(format!("Expected {} amphipods of type {:?}, found {}",
+                               room_size, t, count));
         }
     }
+
+    let state = State::from_positions(&burrow, positions);
+    Ok((burrow, state))
 }
 
-pub fn part1() {
-    use crate::day23::Type::{A, B, C, D};
+/// Inserts the two extra rows found when unfolding the diagram for part 2.
+pub fn unfold(input: &str) -> String {
+    let mut lines: Vec<&str> = input.lines().filter(|line| !line.trim().is_empty()).collect();
+    lines.insert(3, "  #D#C#B#A#");
+    lines.insert(4, "  #D#B#A#C#");
+    lines.join("\n")
+}
 
-    let state = State::new(C, C, A, A, B, D, D, B);
-    search(&state, 2);
+pub fn solve_with(input: &str, strategy: Strategy) -> Result<(Solution, SearchStats), String> {
+    let (burrow, state) = parse(input)?;
+    match search(&burrow, &state, strategy) {
+        (Some(solution), stats) => Ok((solution, stats)),
+        (None, _) => Err(String::from("There's no way to organise the amphipods")),
+    }
 }
 
-pub fn part2() {
-    use crate::day23::Type::{A, B, C, D};
-
-    // #############
-    // #...........#
-    // ###C#A#B#D###
-    //   #D#C#B#A#
-    //   #D#B#A#C#
-    //   #C#A#D#B#
-    //   #########
-
-    let state = State {
-        positions: HashMap::from([
-            (Pos::new(2, 1), C),
-            (Pos::new(2, 2), D),
-            (Pos::new(2, 3), D),
-            (Pos::new(2, 4), C),
-
-            (Pos::new(4, 1), A),
-            (Pos::new(4, 2), C),
-            (Pos::new(4, 3), B),
-            (Pos::new(4, 4), A),
-
-            (Pos::new(6, 1), B),
-            (Pos::new(6, 2), B),
-            (Pos::new(6, 3), A),
-            (Pos::new(6, 4), D),
-
-            (Pos::new(8, 1), D),
-            (Pos::new(8, 2), A),
-            (Pos::new(8, 3), C),
-            (Pos::new(8, 4), B),
-        ])
-    };
+pub fn solve(input: &str) -> Result<Solution, String> {
+    solve_with(input, Strategy::AStar).map(|(solution, _)| solution)
+}
 
-    // #############
-    // #...........#
-    // ###B#C#B#D###
-    //   #D#C#B#A#
-    //   #D#B#A#C#
-    //   #A#D#C#A#
-    //   #########
-
-    // let state = State {
-    //     positions: HashMap::from([
-    //         (Pos::new(2, 1), B),
-    //         (Pos::new(2, 2), D),
-    //         (Pos::new(2, 3), D),
-    //         (Pos::new(2, 4), A),
-    //
-    //         (Pos::new(4, 1), C),
-    //         (Pos::new(4, 2), C),
-    //         (Pos::new(4, 3), B),
-    //         (Pos::new(4, 4), D),
-    //
-    //         (Pos::new(6, 1), B),
-    //         (Pos::new(6, 2), B),
-    //         (Pos::new(6, 3), A),
-    //         (Pos::new(6, 4), C),
-    //
-    //         (Pos::new(8, 1), D),
-    //         (Pos::new(8, 2), A),
-    //         (Pos::new(8, 3), C),
-    //         (Pos::new(8, 4), A),
-    //     ])
-    // };
-
-    search(&state, 4);
+pub fn solve_unfolded(input: &str) -> Result<Solution, String> {
+    solve(&unfold(input))
+}
+
+pub fn part1(input: &str) -> Result<u32, String> {
+    solve(input).map(|solution| solution.energy)
+}
+
+pub fn part2(input: &str) -> Result<u32, String> {
+    solve_unfolded(input).map(|solution| solution.energy)
 }
 
 #[cfg(test)]
 mod test {
-    use crate::day23::Type::{A, B, C, D};
     use super::*;
 
+    const A: Type = Type::A;
+    const B: Type = Type::B;
+    const C: Type = Type::C;
+    const D: Type = Type::D;
+
     #[test]
     fn test_at_destination() {
         // #############
@@ -420,16 +669,45 @@ mod test {
         // ###B#D#C#A###
         //   #A#D#C#B#
         //   #########
+        let burrow = Burrow::standard(2);
         let state = State::new(B, A, D, D, C, C, B, A);
 
-        assert_eq!(false, state.at_destination(&Pos::new(2, 1), 2));
-        assert_eq!(true, state.at_destination(&Pos::new(2, 2), 2));
-        assert_eq!(false, state.at_destination(&Pos::new(4, 1), 2));
-        assert_eq!(false, state.at_destination(&Pos::new(4, 2), 2));
-        assert_eq!(true, state.at_destination(&Pos::new(6, 1), 2));
-        assert_eq!(true, state.at_destination(&Pos::new(6, 2), 2));
-        assert_eq!(false, state.at_destination(&Pos::new(8, 1), 2));
-        assert_eq!(false, state.at_destination(&Pos::new(8, 2), 2));
+        assert_eq!(false, state.at_destination(&burrow, &Pos::new(2, 1)));
+        assert_eq!(true, state.at_destination(&burrow, &Pos::new(2, 2)));
+        assert_eq!(false, state.at_destination(&burrow, &Pos::new(4, 1)));
+        assert_eq!(false, state.at_destination(&burrow, &Pos::new(4, 2)));
+        assert_eq!(true, state.at_destination(&burrow, &Pos::new(6, 1)));
+        assert_eq!(true, state.at_destination(&burrow, &Pos::new(6, 2)));
+        assert_eq!(false, state.at_destination(&burrow, &Pos::new(8, 1)));
+        assert_eq!(false, state.at_destination(&burrow, &Pos::new(8, 2)));
+    }
+
+    #[test]
+    fn test_index() {
+        let burrow = Burrow::standard(4);
+        for i in 0..27 {
+            assert_eq!(i, burrow.index(&burrow.pos(i)));
+        }
+        assert_eq!(Pos::new(6, 3), burrow.pos(burrow.index(&Pos::new(6, 3))));
+
+        let burrow = Burrow::new(9, vec![1, 3, 7], 3).unwrap();
+        for i in 0..18 {
+            assert_eq!(i, burrow.index(&burrow.pos(i)));
+        }
+        assert_eq!(Pos::new(7, 1), burrow.pos(15));
+    }
+
+    #[test]
+    fn test_burrow() {
+        let burrow = Burrow::standard(2);
+        assert_eq!(vec![0, 1, 3, 5, 7, 9, 10], burrow.stops);
+        assert_eq!(vec![1, 10, 100, 1000], burrow.costs);
+
+        assert!(Burrow::new(11, vec![], 2).is_err());
+        assert!(Burrow::new(11, vec![2, 4, 6, 8, 10, 12], 2).is_err());
+        assert!(Burrow::new(11, vec![4, 2], 2).is_err());
+        assert!(Burrow::new(11, vec![2, 4], 0).is_err());
+        assert!(Burrow::new(21, vec![2, 4, 6, 8], 8).is_err());
     }
 
     #[test]
@@ -439,19 +717,18 @@ mod test {
         // ### # # #A###
         //   #B# #C#A#
         //   #########
-        let state = State {
-            positions: HashMap::from([
-                (Pos::new(2, 2), B),
-                (Pos::new(6, 2), C),
-                (Pos::new(8, 1), A),
-                (Pos::new(8, 2), A),
-            ])
-        };
+        let burrow = Burrow::standard(2);
+        let state = State::from_positions(&burrow, [
+            (Pos::new(2, 2), B),
+            (Pos::new(6, 2), C),
+            (Pos::new(8, 1), A),
+            (Pos::new(8, 2), A),
+        ]);
 
-        assert_eq!(None, state.room_open(&A, 2));
-        assert_eq!(Some(2), state.room_open(&B, 2));
-        assert_eq!(Some(1), state.room_open(&C, 2));
-        assert_eq!(None, state.room_open(&D, 2));
+        assert_eq!(None, state.room_open(&burrow, &A));
+        assert_eq!(Some(2), state.room_open(&burrow, &B));
+        assert_eq!(Some(1), state.room_open(&burrow, &C));
+        assert_eq!(None, state.room_open(&burrow, &D));
     }
 
     #[test]
@@ -460,15 +737,14 @@ mod test {
         // #  C        #
         // ### # # # ###
         //      ...
-        let state = State {
-            positions: HashMap::from([
-                (Pos::new(2, 0), C),
-            ])
-        };
-
-        assert_eq!(None, state.clear_path(0, 3));
-        assert_eq!(None, state.clear_path(1, 4));
-        assert_eq!(Some(3), state.clear_path(2, 5));
+        let burrow = Burrow::standard(2);
+        let state = State::from_positions(&burrow, [
+            (Pos::new(2, 0), C),
+        ]);
+
+        assert_eq!(None, state.clear_path(&burrow, 0, 3));
+        assert_eq!(None, state.clear_path(&burrow, 1, 4));
+        assert_eq!(Some(3), state.clear_path(&burrow, 2, 5));
     }
 
     #[test]
@@ -478,17 +754,16 @@ mod test {
         // ### # # #A###
         //   # # #C#A#
         //   #########
-        let state = State {
-            positions: HashMap::from([
-                (Pos::new(6, 2), C),
-                (Pos::new(8, 1), A),
-                (Pos::new(8, 2), A),
-            ])
-        };
+        let burrow = Burrow::standard(2);
+        let state = State::from_positions(&burrow, [
+            (Pos::new(6, 2), C),
+            (Pos::new(8, 1), A),
+            (Pos::new(8, 2), A),
+        ]);
 
-        assert_eq!(true, state.can_leave(&Pos::new(6, 2)));
-        assert_eq!(true, state.can_leave(&Pos::new(8, 1)));
-        assert_eq!(false, state.can_leave(&Pos::new(8, 2)));
+        assert_eq!(true, state.can_leave(&burrow, &Pos::new(6, 2)));
+        assert_eq!(true, state.can_leave(&burrow, &Pos::new(8, 1)));
+        assert_eq!(false, state.can_leave(&burrow, &Pos::new(8, 2)));
     }
 
     #[test]
@@ -498,24 +773,23 @@ mod test {
         // ### # # # ###
         //   # # #C# #
         //   #########
-        let state = State {
-            positions: HashMap::from([
-                (Pos::new(6, 2), C),
-                (Pos::new(0, 0), C),
-                (Pos::new(7, 0), A),
-                (Pos::new(10, 0), B),
-            ])
-        };
-
-        let moves = state.possible_moves(&Pos::new(0, 0), 2);
+        let burrow = Burrow::standard(2);
+        let state = State::from_positions(&burrow, [
+            (Pos::new(6, 2), C),
+            (Pos::new(0, 0), C),
+            (Pos::new(7, 0), A),
+            (Pos::new(10, 0), B),
+        ]);
+
+        let moves = state.possible_moves(&burrow, &Pos::new(0, 0));
         assert_eq!(1, moves.len());
-        assert_eq!(700, moves[0].0);
+        assert_eq!(700, moves[0].0.cost);
 
-        let moves = state.possible_moves(&Pos::new(7, 0), 2);
+        let moves = state.possible_moves(&burrow, &Pos::new(7, 0));
         assert_eq!(1, moves.len());
-        assert_eq!(7, moves[0].0);
+        assert_eq!(7, moves[0].0.cost);
 
-        assert_eq!(0, state.possible_moves(&Pos::new(10, 0), 2).len());
+        assert_eq!(0, state.possible_moves(&burrow, &Pos::new(10, 0)).len());
     }
 
     #[test]
@@ -525,23 +799,84 @@ mod test {
         // ###B# # # ###
         //   #B# #C# #
         //   #########
-        let state = State {
-            positions: HashMap::from([
-                (Pos::new(2, 1), B),
-                (Pos::new(2, 2), B),
-                (Pos::new(5, 0), D),
-                (Pos::new(6, 2), C),
-            ])
-        };
+        let burrow = Burrow::standard(2);
+        let state = State::from_positions(&burrow, [
+            (Pos::new(2, 1), B),
+            (Pos::new(2, 2), B),
+            (Pos::new(5, 0), D),
+            (Pos::new(6, 2), C),
+        ]);
 
         // C is already in its final position.
-        assert_eq!(0, state.possible_moves(&Pos::new(6, 2), 2).len());
+        assert_eq!(0, state.possible_moves(&burrow, &Pos::new(6, 2)).len());
 
         // The bottom B can't get past the top one.
-        assert_eq!(0, state.possible_moves(&Pos::new(2, 2), 2).len());
+        assert_eq!(0, state.possible_moves(&burrow, &Pos::new(2, 2)).len());
 
         // The top B can go to 3 different spots.
-        assert_eq!(3, state.possible_moves(&Pos::new(2, 1), 2).len());
+        assert_eq!(3, state.possible_moves(&burrow, &Pos::new(2, 1)).len());
+    }
+
+    const EXAMPLE: &str = "\
+#############
+#...........#
+###B#C#B#D###
+  #A#D#C#A#
+  #########";
+
+    const SIX_ROOMS: &str = "\
+#################
+#...............#
+###B#A#D#C#F#E###
+  #A#B#C#D#E#F#
+  #############";
+
+    const LONG_HALLWAY: &str = "\
+###############
+#.............#
+#####B#C#A#####
+    #A#C#B#
+    #A#B#C#
+    #######";
+
+    #[test]
+    fn test_parse() {
+        let (burrow, state) = parse(EXAMPLE).unwrap();
+        assert_eq!(Burrow::standard(2), burrow);
+        assert!(State::new(B, A, C, D, B, C, D, A) == state);
+
+        let (burrow, state) = parse(&unfold(EXAMPLE)).unwrap();
+        assert_eq!(Burrow::standard(4), burrow);
+        assert_eq!(Some(D), state.get(&burrow, &Pos::new(2, 2)));
+        assert_eq!(Some(B), state.get(&burrow, &Pos::new(4, 3)));
+        assert_eq!(Some(A), state.get(&burrow, &Pos::new(2, 4)));
+
+        // Amphipods in the hallway are fine too.
+        let (burrow, state) = parse("\
+#############
+#.A.........#
+###.#B#C#D###
+  #A#B#C#D#
+  #########").unwrap();
+        assert_eq!(Some(A), state.get(&burrow, &Pos::new(1, 0)));
+
+        let (burrow, _) = parse(SIX_ROOMS).unwrap();
+        assert_eq!(15, burrow.hallway_length);
+        assert_eq!(vec![2, 4, 6, 8, 10, 12], burrow.rooms);
+        assert_eq!(2, burrow.room_size());
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(parse("#############\n#...........#").is_err());
+        assert!(parse(&EXAMPLE.replace('D', "E")).is_err());
+        assert!(parse(&EXAMPLE.replace("#C#A#", "#C#B#")).is_err());
+        assert!(parse(&EXAMPLE.replace("###B#C", "#B##C")).is_err());
+        assert!(parse(&EXAMPLE.replace("  #A#D#C#A#", " #A#D#C#A##")).is_err());
+        assert!(parse(&EXAMPLE.replace("#...........#", "#.....#.....#")).is_err());
+        assert!(parse(&EXAMPLE.replace("#...........#", "#.....#")).is_err());
+        assert!(parse(&EXAMPLE.replace("#...........#", "#...A.......#")
+            .replace("#B#D###", "#.#D###")).is_err());
     }
 
     #[test]
@@ -553,13 +888,119 @@ mod test {
         //   #########
         let state = State::new(B, A, C, D, B, C, D, A);
 
-        search(&state, 2);
+        let (solution, _) = search(&Burrow::standard(2), &state, Strategy::AStar);
+        assert_eq!(Some(12521), solution.map(|s| s.energy));
+        assert_eq!(Ok(12521), super::part1(EXAMPLE));
+    }
+
+    #[test]
+    fn given_example_moves() {
+        let solution = solve(EXAMPLE).unwrap();
+        assert_eq!(12521, solution.moves.iter().map(|step| step.cost).sum::<u32>());
+
+        // Replaying the moves should organise the amphipods. The order of moves isn't fixed since
+        // there are several equally good solutions.
+        let burrow = &solution.burrow;
+        let finished = solution.moves.iter().fold(solution.start.clone(), |state, step| {
+            assert_eq!(Some(step.pod), state.get(burrow, &step.from));
+            state.new_state(burrow, &step.from, &step.to)
+        });
+        assert!(finished.is_finished(burrow));
+
+        let render = solution.render();
+        assert!(render.starts_with(EXAMPLE));
+        assert!(render.ends_with("\
+#############
+#...........#
+###A#B#C#D###
+  #A#B#C#D#
+  #########
+"));
+        assert_eq!(solution.moves.len() + 1, render.matches("#############").count());
+    }
+
+    #[test]
+    fn draw_round_trips() {
+        for diagram in [EXAMPLE, &unfold(EXAMPLE), SIX_ROOMS, LONG_HALLWAY] {
+            let (burrow, state) = parse(diagram).unwrap();
+            assert_eq!(diagram, burrow.draw(&state));
+        }
+    }
+
+    #[test]
+    fn other_burrows() {
+        // Moving A out of the way first is cheapest: 2 + 40 + 4.
+        assert_eq!(Ok(46), super::part1("\
+#######
+#.....#
+###B#A###
+  #####"));
+
+        let solution = solve(SIX_ROOMS).unwrap();
+        let burrow = &solution.burrow;
+        let finished = solution.moves.iter().fold(solution.start.clone(), |state, step| {
+            state.new_state(burrow, &step.from, &step.to)
+        });
+        assert!(finished.is_finished(burrow));
+        // Each pair swaps by the cheaper pod stepping aside: 46 + 4600 + 460000.
+        assert_eq!(464646, solution.energy);
+
+        assert_eq!(Ok(1128), super::part1(LONG_HALLWAY));
+    }
+
+    /// Checks that the estimate from every state along a best solution is no more than the energy
+    /// it actually takes to finish from there.
+    fn assert_admissible(solution: &Solution) {
+        let burrow = &solution.burrow;
+        let mut state = solution.start.clone();
+        let mut remaining = solution.energy;
+        for step in &solution.moves {
+            assert!(state.distance_estimate(burrow) <= remaining);
+            state = state.new_state(burrow, &step.from, &step.to);
+            remaining -= step.cost;
+        }
+        assert_eq!(0, state.distance_estimate(burrow));
+    }
+
+    #[test]
+    fn test_distance_estimate() {
+        let (burrow, state) = parse(EXAMPLE).unwrap();
+        assert_eq!(12499, state.distance_estimate(&burrow));
+        let (solution, _) = solve_with(EXAMPLE, Strategy::Dijkstra).unwrap();
+        assert_eq!(12521, solution.energy);
+        assert_admissible(&solution);
+
+        // The top A and B have to step out of their rooms to let the pods below them out.
+        let burrow = Burrow::standard(2);
+        let state = State::new(A, B, B, A, C, C, D, D);
+        assert_eq!(3 + 40 + 30 + 4 + 3 + 30, state.distance_estimate(&burrow));
+        let (solution, _) = search(&burrow, &state, Strategy::Dijkstra);
+        let solution = solution.unwrap();
+        assert_eq!(112, solution.energy);
+        assert_admissible(&solution);
+    }
+
+    #[test]
+    fn strategies_agree() {
+        let (dijkstra, dijkstra_stats) = solve_with(EXAMPLE, Strategy::Dijkstra).unwrap();
+        let (a_star, a_star_stats) = solve_with(EXAMPLE, Strategy::AStar).unwrap();
+
+        assert_eq!(12521, dijkstra.energy);
+        assert_eq!(12521, a_star.energy);
+        assert!(a_star_stats.expanded < dijkstra_stats.expanded);
+        assert!(a_star_stats.max_heap_size <= dijkstra_stats.max_heap_size);
+    }
+
+    #[test]
+    fn given_example_part2() {
+        assert_eq!(Ok(44169), super::part2(EXAMPLE));
     }
 
     #[test]
     fn part1() {
         let state = State::new(C, C, A, A, B, D, D, B);
-        search(&state, 2);
+        let (solution, _) = search(&Burrow::standard(2), &state, Strategy::AStar);
+        assert_eq!(Some(11536), solution.map(|s| s.energy));
     }
 
     #[test]
@@ -569,14 +1010,13 @@ mod test {
         // ### # # # ###
         //   #C#D#A#B#
         //   #########
-        let state = State {
-            positions: HashMap::from([
-                (Pos::new(2, 2), C),  // none, h1 (just x), h2 (x and y), h3 (* cost)
-                (Pos::new(6, 2), A),  // 72, 70
-                (Pos::new(4, 2), D),  // 1363, 1519, 69
-                (Pos::new(8, 2), B),  // 22755, 24216, 23980, 469, 7324
-            ])
-        };
-        search(&state, 2);
+        let burrow = Burrow::standard(2);
+        let state = State::from_positions(&burrow, [
+            (Pos::new(2, 2), C),  // none, h1 (just x), h2 (x and y), h3 (* cost)
+            (Pos::new(6, 2), A),  // 72, 70
+            (Pos::new(4, 2), D),  // 1363, 1519, 69
+            (Pos::new(8, 2), B),  // 22755, 24216, 23980, 469, 7324
+        ]);
+        assert_eq!(Some(8910), search(&burrow, &state, Strategy::AStar).0.map(|s| s.energy));
     }
 }
\ No newline at end of file
@@ -1,7 +1,11 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::io::{BufRead, Write};
+use std::rc::Rc;
 
-type Int = i32;
+type Int = i64;
 
+#[cfg(test)]
 fn get_index(c: char) -> usize {
     match c {
         'w' => 0,
@@ -12,17 +16,17 @@ fn get_index(c: char) -> usize {
     }
 }
 
-#[derive(Clone, Copy)]
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
 enum Variable { W, X, Y, Z }
 
 impl Variable {
-    fn from(c: char) -> Variable {
-        match c {
-            'w' => Variable::W,
-            'x' => Variable::X,
-            'y' => Variable::Y,
-            'z' => Variable::Z,
-            _ => panic!("Unknown variable: {}", c),
+    fn parse(s: &str) -> Option<Variable> {
+        match s {
+            "w" => Some(Variable::W),
+            "x" => Some(Variable::X),
+            "y" => Some(Variable::Y),
+            "z" => Some(Variable::Z),
+            _ => None,
         }
     }
 
@@ -40,22 +44,22 @@ impl Variable {
     }
 }
 
-#[derive(Clone, Copy)]
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
 enum VarOrLiteral {
     Variable(Variable),
-    Literal(i8)  // Looking at the input, this should cover us.
+    Literal(Int),
 }
 
 impl VarOrLiteral {
     fn value(&self, state: &[Int; 4]) -> Int {
         match self {
             VarOrLiteral::Variable(variable) => variable.value(state),
-            VarOrLiteral::Literal(value) => *value as Int
+            VarOrLiteral::Literal(value) => *value,
         }
     }
 }
 
-#[derive(Clone, Copy)]
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
 enum Command {
     Inp(Variable),
     Add(Variable, VarOrLiteral),
@@ -65,82 +69,374 @@ enum Command {
     Eql(Variable, VarOrLiteral),
 }
 
-fn parse_program(input: &str) -> Vec<Command> {
-    let mut program = Vec::new();
-    let mut stream = input.split(&['\n', ' '][..]);
+fn parse_program(input: &str) -> Result<Vec<Command>, String> {
+    input.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(n, line)| {
+            let error = || format!("Could not parse line {}: '{}'", n + 1, line);
+            let mut tokens = line.split_whitespace();
 
-    while let Some(command) = stream.next() {
-        if command.is_empty() { continue; }
+            let command = tokens.next().ok_or_else(error)?;
+            let a = tokens.next().and_then(Variable::parse).ok_or_else(error)?;
 
-        let a_char = stream.next().unwrap().chars().next().unwrap();
-        let a = Variable::from(a_char);
+            if command == "inp" {
+                return match tokens.next() {
+                    None => Ok(Command::Inp(a)),
+                    Some(_) => Err(error()),
+                };
+            }
 
-        if command == "inp" {
-            program.push(Command::Inp(a));
-            continue;
-        }
+            // Either b is a variable, or it's a literal.
+            let b_str = tokens.next().ok_or_else(error)?;
+            let b = match Variable::parse(b_str) {
+                Some(variable) => VarOrLiteral::Variable(variable),
+                None => VarOrLiteral::Literal(b_str.parse().map_err(|_| error())?),
+            };
 
-        let b_str = stream.next().unwrap();
-        // Either b is a variable, or it's a literal.
-        let b: VarOrLiteral = if b_str.len() == 1 && "wxyz".contains(b_str) {
-            VarOrLiteral::Variable(Variable::from(b_str.chars().next().unwrap()))
-        } else {
-            VarOrLiteral::Literal(b_str.parse().unwrap())
-        };
+            if tokens.next().is_some() {
+                return Err(error());
+            }
 
-        program.push(match command {
-            "add" => Command::Add(a, b),
-            "mul" => Command::Mul(a, b),
-            "div" => Command::Div(a, b),
-            "mod" => Command::Mod(a, b),
-            "eql" => Command::Eql(a, b),
-            _ => { panic!("Unknown command: {}", command) }
-        });
+            match command {
+                "add" => Ok(Command::Add(a, b)),
+                "mul" => Ok(Command::Mul(a, b)),
+                "div" => Ok(Command::Div(a, b)),
+                "mod" => Ok(Command::Mod(a, b)),
+                "eql" => Ok(Command::Eql(a, b)),
+                _ => Err(error()),
+            }
+        })
+        .collect()
+}
+
+fn add(a: Int, b: Int) -> Result<Int, &'static str> {
+    a.checked_add(b).ok_or("overflow")
+}
+
+fn multiply(a: Int, b: Int) -> Result<Int, &'static str> {
+    a.checked_mul(b).ok_or("overflow")
+}
+
+/// Division, which is invalid if `b` is 0.
+fn divide(a: Int, b: Int) -> Result<Int, &'static str> {
+    if b == 0 {
+        Err("division by zero")
+    } else {
+        a.checked_div(b).ok_or("overflow")
+    }
+}
+
+/// Modulo, which is invalid if `a` is negative or `b` isn't positive.
+fn modulo(a: Int, b: Int) -> Result<Int, &'static str> {
+    if a < 0 {
+        Err("modulo of a negative number")
+    } else if b <= 0 {
+        Err("modulo by a number that isn't positive")
+    } else {
+        Ok(a % b)
     }
+}
 
-    program
+fn read_input(input: &[u8], input_index: &mut usize) -> Result<Int, &'static str> {
+    let value = input.get(*input_index).ok_or("ran out of input")?;
+    *input_index += 1;
+    Ok(*value as Int)
 }
 
-fn run_program(program: &[Command], input: &[u8]) -> [Int; 4] {
+#[cfg(test)]
+fn run_program(program: &[Command], input: &[u8]) -> Result<[Int; 4], String> {
     run_program_with_state(program, input, [0, 0, 0, 0])
 }
 
-fn run_program_with_state(program: &[Command], input: &[u8], state: [Int; 4]) -> [Int; 4] {
+/// Interprets the program directly. `Compiled` is used for running programs many times, and is
+/// checked against this.
+#[cfg(test)]
+fn run_program_with_state(program: &[Command], input: &[u8], state: [Int; 4])
+    -> Result<[Int; 4], String> {
     let mut input_index = 0;
     let mut state = state;
 
-    for command in program {
-        match command {
-            Command::Inp(v) => {
-                state[v.index()] = input[input_index] as Int;
-                input_index += 1;
+    for (index, command) in program.iter().enumerate() {
+        execute(command, &mut state, input, &mut input_index)
+            .map_err(|reason| format!("Instruction {}: {}", index, reason))?;
+    }
+
+    Ok(state)
+}
+
+fn execute(command: &Command, state: &mut [Int; 4], input: &[u8], input_index: &mut usize)
+    -> Result<(), &'static str> {
+    match command {
+        Command::Inp(v) => {
+            state[v.index()] = read_input(input, input_index)?;
+        }
+        Command::Add(v, o) => {
+            state[v.index()] = add(v.value(state), o.value(state))?;
+        }
+        Command::Mul(v, o) => {
+            state[v.index()] = multiply(v.value(state), o.value(state))?;
+        }
+        Command::Div(v, o) => {
+            state[v.index()] = divide(v.value(state), o.value(state))?;
+        }
+        Command::Mod(v, o) => {
+            state[v.index()] = modulo(v.value(state), o.value(state))?;
+        }
+        Command::Eql(v, o) => {
+            state[v.index()] = (v.value(state) == o.value(state)) as Int;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+fn parse_and_run_program(program: &str, input: &[u8]) -> Result<[Int; 4], String> {
+    let program = parse_program(program)?;
+    run_program(&program, input)
+}
+
+/// An operand of a compiled instruction, with variables that are known to be constant at that
+/// point replaced by their value.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum Operand {
+    Register(usize),
+    Constant(Int),
+}
+
+impl Operand {
+    fn value(&self, state: &[Int; 4]) -> Int {
+        match self {
+            Operand::Register(r) => state[*r],
+            Operand::Constant(c) => *c,
+        }
+    }
+
+    fn register(&self) -> Option<usize> {
+        match self {
+            Operand::Register(r) => Some(*r),
+            Operand::Constant(_) => None,
+        }
+    }
+}
+
+/// A compiled instruction. Unlike `Command`, the destination doesn't have to be the left operand.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum Op {
+    Inp(usize),
+    Set(usize, Operand),
+    Add(usize, Operand, Operand),
+    Mul(usize, Operand, Operand),
+    Div(usize, Operand, Operand),
+    Mod(usize, Operand, Operand),
+    Eql(usize, Operand, Operand),
+}
+
+impl Op {
+    fn destination(&self) -> usize {
+        match self {
+            Op::Inp(d) | Op::Set(d, _) | Op::Add(d, _, _) | Op::Mul(d, _, _)
+            | Op::Div(d, _, _) | Op::Mod(d, _, _) | Op::Eql(d, _, _) => *d,
+        }
+    }
+
+    fn operands(&self) -> Vec<Operand> {
+        match self {
+            Op::Inp(_) => vec![],
+            Op::Set(_, a) => vec![*a],
+            Op::Add(_, a, b) | Op::Mul(_, a, b) | Op::Div(_, a, b) | Op::Mod(_, a, b)
+            | Op::Eql(_, a, b) => vec![*a, *b],
+        }
+    }
+
+    /// Whether the instruction can be dropped if nothing reads its result. Inputs can't be, since
+    /// they use up a digit, and neither can anything that might be invalid, which includes any
+    /// addition or multiplication of a register since it might overflow.
+    fn is_removable(&self) -> bool {
+        match self {
+            Op::Inp(_) | Op::Add(_, _, _) | Op::Mul(_, _, _) => false,
+            Op::Div(_, _, b) => matches!(b, Operand::Constant(c) if *c != 0 && *c != -1),
+            Op::Mod(_, a, b) => matches!((a, b), (Operand::Constant(a), Operand::Constant(b))
+                if *a >= 0 && *b > 0),
+            _ => true,
+        }
+    }
+}
+
+/// Works out the result of an instruction on constants, unless it would overflow or be invalid.
+fn fold(command: &Command, a: Int, b: Int) -> Option<Int> {
+    match command {
+        Command::Inp(_) => None,
+        Command::Add(_, _) => a.checked_add(b),
+        Command::Mul(_, _) => a.checked_mul(b),
+        Command::Div(_, _) => divide(a, b).ok(),
+        Command::Mod(_, _) => modulo(a, b).ok(),
+        Command::Eql(_, _) => Some((a == b) as Int),
+    }
+}
+
+/// A program compiled for running many times. Constants are folded, instructions that don't
+/// change anything are dropped, and so are instructions whose results are never used.
+#[derive(Debug)]
+pub struct Compiled {
+    ops: Vec<Op>,
+    /// The index of the instruction in the original program each op came from, for errors.
+    indices: Vec<usize>,
+    /// The registers that are read before they're written, so the result depends on their values
+    /// in the starting state.
+    reads: [bool; 4],
+}
+
+impl Compiled {
+    /// Compiles the program, given which registers are known to start with a particular value and
+    /// which registers are needed at the end.
+    fn new(program: &[Command], known: [Option<Int>; 4], outputs: [bool; 4]) -> Compiled {
+        let mut values = known;
+        // Each op, along with the index of the instruction it came from.
+        let mut ops: Vec<(usize, Op)> = Vec::new();
+        // Which registers have been written by an op, so no longer hold their starting value.
+        let mut written = [false; 4];
+
+        for (index, command) in program.iter().enumerate() {
+            let (v, o) = match command {
+                Command::Inp(v) => {
+                    ops.push((index, Op::Inp(v.index())));
+                    values[v.index()] = None;
+                    written[v.index()] = true;
+                    continue;
+                }
+                Command::Add(v, o) | Command::Mul(v, o) | Command::Div(v, o)
+                | Command::Mod(v, o) | Command::Eql(v, o) => (v.index(), o),
+            };
+
+            let operand = |r: usize| values[r].map_or(Operand::Register(r), Operand::Constant);
+            let a = operand(v);
+            let b = match o {
+                VarOrLiteral::Variable(variable) => operand(variable.index()),
+                VarOrLiteral::Literal(value) => Operand::Constant(*value),
+            };
+
+            if let (Operand::Constant(a), Operand::Constant(b)) = (a, b) {
+                if let Some(result) = fold(command, a, b) {
+                    values[v] = Some(result);
+                    continue;
+                }
             }
-            Command::Add(v, o) => {
-                state[v.index()] = v.value(&state) + o.value(&state);
+
+            // Either the result is known without knowing the register, or the instruction leaves
+            // it as it is, or it copies the other operand.
+            let (known, same, copy) = match (command, a, b) {
+                (Command::Mul(_, _), Operand::Constant(0), _)
+                | (Command::Mul(_, _), _, Operand::Constant(0)) => (Some(0), false, None),
+                (Command::Add(_, _), _, Operand::Constant(0))
+                | (Command::Mul(_, _), _, Operand::Constant(1))
+                | (Command::Div(_, _), _, Operand::Constant(1)) => (None, true, None),
+                (Command::Add(_, _), Operand::Constant(0), other)
+                | (Command::Mul(_, _), Operand::Constant(1), other) => (None, false, Some(other)),
+                _ => (None, false, None),
+            };
+
+            if known.is_some() {
+                values[v] = known;
+                continue;
             }
-            Command::Mul(v, o) => {
-                state[v.index()] = v.value(&state) * o.value(&state);
+            if same {
+                continue;
             }
-            Command::Div(v, o) => {
-                state[v.index()] = v.value(&state) / o.value(&state);
+
+            values[v] = None;
+            written[v] = true;
+            ops.push((index, match (copy, command) {
+                (Some(other), _) => Op::Set(v, other),
+                (None, Command::Add(_, _)) => Op::Add(v, a, b),
+                (None, Command::Mul(_, _)) => Op::Mul(v, a, b),
+                (None, Command::Div(_, _)) => Op::Div(v, a, b),
+                (None, Command::Mod(_, _)) => Op::Mod(v, a, b),
+                (None, _) => Op::Eql(v, a, b),
+            }));
+        }
+
+        // Registers that ended up with a known value still need setting, if they're needed and
+        // something overwrote them or the value is different from the one they started with.
+        for r in 0..4 {
+            if let Some(value) = values[r] {
+                if outputs[r] && (written[r] || known[r] != Some(value)) {
+                    ops.push((program.len(), Op::Set(r, Operand::Constant(value))));
+                }
             }
-            Command::Mod(v, o) => {
-                state[v.index()] = v.value(&state) % o.value(&state);
+        }
+
+        let (indices, ops): (Vec<usize>, Vec<Op>) =
+            Compiled::remove_dead_stores(ops, outputs).into_iter().unzip();
+        let reads = Compiled::find_reads(&ops);
+        Compiled { ops, indices, reads }
+    }
+
+    /// Working backwards, drops instructions whose results are overwritten before being read.
+    fn remove_dead_stores(ops: Vec<(usize, Op)>, outputs: [bool; 4]) -> Vec<(usize, Op)> {
+        let mut live = outputs;
+        let mut kept = Vec::new();
+
+        for (index, op) in ops.into_iter().rev() {
+            let destination = op.destination();
+            if !live[destination] && op.is_removable() { continue; }
+
+            live[destination] = false;
+            for r in op.operands().iter().filter_map(|operand| operand.register()) {
+                live[r] = true;
             }
-            Command::Eql(v, o) => {
-                state[v.index()] = (v.value(&state) == o.value(&state)) as Int;
+            kept.push((index, op));
+        }
+
+        kept.reverse();
+        kept
+    }
+
+    fn find_reads(ops: &[Op]) -> [bool; 4] {
+        let mut written = [false; 4];
+        let mut reads = [false; 4];
+
+        for op in ops {
+            for r in op.operands().iter().filter_map(|operand| operand.register()) {
+                reads[r] |= !written[r];
             }
+            written[op.destination()] = true;
         }
+
+        reads
     }
 
-    state
-}
+    fn run(&self, input: &[u8], state: [Int; 4]) -> Result<[Int; 4], String> {
+        let mut input_index = 0;
+        let mut state = state;
 
-fn parse_and_run_program(program: &str, input: &[u8]) -> [Int; 4] {
-    let program = parse_program(program);
-    run_program(&program, input)
+        for (op, index) in self.ops.iter().zip(&self.indices) {
+            let error = |reason| format!("Instruction {}: {}", index, reason);
+
+            match op {
+                Op::Inp(d) => state[*d] = read_input(input, &mut input_index).map_err(error)?,
+                Op::Set(d, a) => state[*d] = a.value(&state),
+                Op::Add(d, a, b) => {
+                    state[*d] = add(a.value(&state), b.value(&state)).map_err(error)?
+                }
+                Op::Mul(d, a, b) => {
+                    state[*d] = multiply(a.value(&state), b.value(&state)).map_err(error)?
+                }
+                Op::Div(d, a, b) => {
+                    state[*d] = divide(a.value(&state), b.value(&state)).map_err(error)?
+                }
+                Op::Mod(d, a, b) => {
+                    state[*d] = modulo(a.value(&state), b.value(&state)).map_err(error)?
+                }
+                Op::Eql(d, a, b) => state[*d] = (a.value(&state) == b.value(&state)) as Int,
+            }
+        }
+
+        Ok(state)
+    }
 }
 
+#[cfg(test)]
 fn num_to_digits(num: u64) -> [u8; 14] {
     let mut w = num;
     let mut column = 1;
@@ -156,53 +452,669 @@ fn num_to_digits(num: u64) -> [u8; 14] {
     digits
 }
 
-pub fn part1(program: &str) {
-    // let mut i = 99999u64;
-    // let mut i = 99999999999999u64;
-    let program = parse_program(program);
+/// The largest and smallest model numbers accepted, or while searching, the largest and smallest
+/// inputs that get to a z value.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct ModelNumbers {
+    pub largest: u64,
+    pub smallest: u64,
+}
+
+/// What the part of the program divides z by, if anything.
+fn z_divisor(part: &[Command]) -> Int {
+    part.iter()
+        .map(|command| match command {
+            Command::Div(Variable::Z, VarOrLiteral::Literal(d)) => d.abs().max(1),
+            _ => 1,
+        })
+        .product()
+}
+
+/// Whether the only way the part of the program can make z smaller is dividing it by a positive
+/// literal, given that z isn't negative, the other registers start at 0 and inputs are digits from
+/// 1 to 9. This works out the range of values each register can hold to check that z is only ever
+/// multiplied by positive numbers and added to with numbers that aren't negative.
+fn only_divides_z(part: &[Command]) -> bool {
+    let mut ranges: [(Int, Int); 4] = [(0, 0), (0, 0), (0, 0), (0, Int::MAX)];
+
+    for command in part {
+        let (v, o) = match command {
+            Command::Inp(Variable::Z) => return false,
+            Command::Inp(v) => {
+                ranges[v.index()] = (1, 9);
+                continue;
+            }
+            Command::Add(v, o) | Command::Mul(v, o) | Command::Div(v, o)
+            | Command::Mod(v, o) | Command::Eql(v, o) => (v.index(), o),
+        };
+        let a = ranges[v];
+        let b = match o {
+            VarOrLiteral::Variable(variable) => ranges[variable.index()],
+            VarOrLiteral::Literal(value) => (*value, *value),
+        };
+
+        if v == Variable::Z.index() {
+            let shrinks = match command {
+                Command::Add(_, _) => b.0 < 0,
+                Command::Mul(_, _) => b.0 < 1,
+                Command::Div(_, VarOrLiteral::Literal(d)) => *d < 1,
+                _ => true,
+            };
+            if shrinks {
+                return false;
+            }
+        }
+
+        let corners = |f: fn(Int, Int) -> Int| {
+            let values = [f(a.0, b.0), f(a.0, b.1), f(a.1, b.0), f(a.1, b.1)];
+            (*values.iter().min().unwrap(), *values.iter().max().unwrap())
+        };
+        ranges[v] = match command {
+            Command::Add(_, _) => (a.0.saturating_add(b.0), a.1.saturating_add(b.1)),
+            Command::Mul(_, _) => corners(Int::saturating_mul),
+            Command::Div(_, _) if b.0 > 0 || b.1 < 0 => corners(Int::saturating_div),
+            Command::Mod(_, _) => (0, b.1.max(1) - 1),
+            Command::Eql(_, _) => (0, 1),
+            _ => (Int::MIN, Int::MAX),
+        };
+    }
+
+    true
+}
+
+/// Finds the largest and smallest model numbers that MONAD accepts by running each digit's part
+/// of the program on every z value the previous parts can produce.
+pub fn search_model_numbers(program: &str) -> Result<ModelNumbers, String> {
+    let program = parse_program(program)?;
 
     let mut program_parts : Vec<Vec<Command>> = Vec::new();
 
     for command in program.iter() {
         if let Command::Inp(_) = &command {
             program_parts.push(vec![*command]);
+        } else if let Some(part) = program_parts.last_mut() {
+            part.push(*command);
         } else {
-            program_parts.last_mut().unwrap().push(*command);
+            return Err(String::from("The program should start with an inp"));
         }
     }
+    if program_parts.is_empty() {
+        return Err(String::from("The program has no inp instructions"));
+    }
 
-    // This is the state that is carried over from one part to another.
-    let mut possible_z_values: HashSet<Int> = HashSet::from([0]);
-    // let mut best_for_z_at_position: HashMap<(usize, Int), u64> = HashMap::new();
-    let mut best_for_prev_z: HashMap<Int, u64> = HashMap::new();
+    // In MONAD, each part can only shrink z by dividing it, so if z is bigger than what the
+    // remaining parts divide it by it can never get back to 0. This keeps the number of z values
+    // manageable. Other programs are searched without the limit.
+    let mut max_z: Vec<Option<Int>> = vec![None; program_parts.len()];
+    if program_parts.iter().all(|part| only_divides_z(part)) {
+        max_z[program_parts.len() - 1] = Some(1);
+        for n in (0..(program_parts.len() - 1)).rev() {
+            let divisor = z_divisor(&program_parts[n + 1]);
+            max_z[n] = max_z[n + 1].and_then(|max| max.checked_mul(divisor));
+        }
+    }
 
+    // This is the state that is carried over from one part to another. The only register that
+    // matters between parts is z, since the others are all reset before being read. Knowing that
+    // they start at 0 lets more of each part be worked out ahead of time.
+    let mut compiled_parts = Vec::new();
     for (n, part) in program_parts.iter().enumerate() {
-        let mut next_z_values = HashSet::new();
-        let mut best_for_z = HashMap::new();
+        let reads = Compiled::new(part, [None; 4], [true; 4]).reads;
+        if let Some(r) = (0..3).find(|r| reads[*r]) {
+            let register = "wxyz".as_bytes()[r] as char;
+            return Err(format!("Part {} reads {} before writing it", n, register));
+        }
+        compiled_parts.push(Compiled::new(part, [Some(0), Some(0), Some(0), None],
+                                          [false, false, false, true]));
+    }
+
+    let mut best_for_prev_z: HashMap<Int, ModelNumbers> = HashMap::from([
+        (0, ModelNumbers { largest: 0, smallest: 0 })
+    ]);
+
+    for (n, part) in compiled_parts.iter().enumerate() {
+        let mut best_for_z: HashMap<Int, ModelNumbers> = HashMap::new();
 
         for i in 1..10 {
-            for prev_z in possible_z_values.iter() {
-                let z = run_program_with_state(part, &[i], [0, 0, 0, *prev_z])[3];
-
-                // Comment out to get part 1
-                // TODO: Split part 1 and 2.
-                if best_for_z.contains_key(&z) { continue; }
-
-                if n == 0 {
-                    best_for_z.insert(z, i as u64);
-                } else {
-                    let prev = best_for_prev_z.get(prev_z).unwrap();
-                    best_for_z.insert(z, prev * 10 + (i as u64));
-                }
+            for (prev_z, prev) in best_for_prev_z.iter() {
+                // Inputs that make the ALU crash aren't valid model numbers.
+                let z = match part.run(&[i], [0, 0, 0, *prev_z]) {
+                    Ok(state) => state[3],
+                    Err(_) => continue,
+                };
+                if max_z[n].is_some_and(|max| z >= max) { continue; }
+
+                let largest = prev.largest * 10 + (i as u64);
+                let smallest = prev.smallest * 10 + (i as u64);
 
-                next_z_values.insert(z);
+                best_for_z.entry(z)
+                    .and_modify(|best| {
+                        best.largest = best.largest.max(largest);
+                        best.smallest = best.smallest.min(smallest);
+                    })
+                    .or_insert(ModelNumbers { largest, smallest });
             }
         }
 
         best_for_prev_z = best_for_z;
-        possible_z_values = next_z_values;
-        println!("Done {}, {}, {:?}", n, possible_z_values.len(), best_for_prev_z.get(&0));
     }
+
+    best_for_prev_z.get(&0)
+        .copied()
+        .ok_or(String::from("The program doesn't accept any model number"))
+}
+
+/// MONAD is made of 14 copies of this block, one per digit, differing only in the three `?`s.
+const BLOCK: [&str; 18] = [
+    "inp w", "mul x 0", "add x z", "mod x 26", "div z ?", "add x ?", "eql x w", "eql x 0",
+    "mul y 0", "add y 25", "mul y x", "add y 1", "mul z y", "mul y 0", "add y w", "add y ?",
+    "mul y x", "add z y",
+];
+const DIGITS: usize = 14;
+
+/// The parameters of one of MONAD's blocks.
+///
+/// Each block treats z as a stack of base 26 digits. With `div z 1` it pushes `w + add_y`. With
+/// `div z 26` it pops the top value and, unless `w` equals that value plus `add_x`, pushes
+/// `w + add_y` back on. The stack is only empty at the end if no pop pushes anything back.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Block {
+    pub div_z: Int,
+    pub add_x: Int,
+    pub add_y: Int,
+}
+
+/// The digit at `pop` has to be the digit at `push` plus `offset`.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Constraint {
+    pub push: usize,
+    pub pop: usize,
+    pub offset: Int,
+}
+
+/// Extracts the parameters of each block, checking the program matches MONAD's structure.
+pub fn parse_blocks(program: &str) -> Result<Vec<Block>, String> {
+    let lines: Vec<&str> = program.lines()
+        .map(|line| line.trim())
+        .filter(|line| !line.is_empty())
+        .collect();
+
+    if lines.len() != BLOCK.len() * DIGITS {
+        return Err(format!("Expected {} instructions ({} blocks of {}), found {}",
+                           BLOCK.len() * DIGITS, DIGITS, BLOCK.len(), lines.len()));
+    }
+
+    lines.chunks(BLOCK.len())
+        .enumerate()
+        .map(|(n, block)| {
+            let mut parameters = Vec::new();
+
+            for (i, (line, expected)) in block.iter().zip(BLOCK.iter()).enumerate() {
+                let index = n * BLOCK.len() + i;
+                let mismatch = || format!("Instruction {} is '{}', but expected '{}'",
+                                          index, line, expected);
+
+                match expected.strip_suffix('?') {
+                    Some(prefix) => {
+                        let value = line.strip_prefix(prefix)
+                            .and_then(|value| value.parse().ok())
+                            .ok_or_else(mismatch)?;
+                        parameters.push(value);
+                    }
+                    None if line == expected => {}
+                    None => return Err(mismatch()),
+                }
+            }
+
+            Ok(Block { div_z: parameters[0], add_x: parameters[1], add_y: parameters[2] })
+        })
+        .collect()
+}
+
+/// Pairs up the blocks that push onto z with the ones that pop them off again.
+pub fn constraints(blocks: &[Block]) -> Result<Vec<Constraint>, String> {
+    let mut stack: Vec<(usize, &Block)> = Vec::new();
+    let mut constraints = Vec::new();
+
+    for (n, block) in blocks.iter().enumerate() {
+        match block.div_z {
+            1 => {
+                // If x could match w the block might not push, which we can't reason about.
+                if block.add_x < 10 {
+                    return Err(format!("Block {} might not push (add x {})", n, block.add_x));
+                }
+                stack.push((n, block));
+            }
+            26 => {
+                let (push, pushed) = stack.pop()
+                    .ok_or(format!("Block {} pops from an empty stack", n))?;
+                let offset = pushed.add_y + block.add_x;
+
+                if offset.abs() > 8 {
+                    return Err(format!("No pair of digits for blocks {} and {} differ by {}",
+                                       push, n, offset));
+                }
+                constraints.push(Constraint { push, pop: n, offset });
+            }
+            other => return Err(format!("Block {} divides z by {}", n, other)),
+        }
+    }
+
+    if !stack.is_empty() {
+        return Err(format!("{} blocks are never popped", stack.len()));
+    }
+
+    Ok(constraints)
+}
+
+/// Finds the largest and smallest model numbers directly from the constraints between digits.
+pub fn analyse(program: &str) -> Result<ModelNumbers, String> {
+    let constraints = constraints(&parse_blocks(program)?)?;
+
+    let mut largest = [0; DIGITS];
+    let mut smallest = [0; DIGITS];
+
+    for Constraint { push, pop, offset } in constraints {
+        // Make whichever digit is larger 9 for the largest number, and the smaller one 1 for the
+        // smallest.
+        largest[push] = 9.min(9 - offset);
+        largest[pop] = largest[push] + offset;
+        smallest[push] = 1.max(1 - offset);
+        smallest[pop] = smallest[push] + offset;
+    }
+
+    let to_number = |digits: [Int; DIGITS]| digits.iter().fold(0, |n, d| n * 10 + *d as u64);
+    Ok(ModelNumbers { largest: to_number(largest), smallest: to_number(smallest) })
+}
+
+pub fn part1(program: &str) -> Result<u64, String> {
+    analyse(program).map(|numbers| numbers.largest)
+}
+
+pub fn part2(program: &str) -> Result<u64, String> {
+    analyse(program).map(|numbers| numbers.smallest)
+}
+
+/// The source of each instruction, in the same order as `parse_program` returns them.
+fn source_lines(program: &str) -> Vec<&str> {
+    program.lines().map(|line| line.trim()).filter(|line| !line.is_empty()).collect()
+}
+
+/// Runs a program one instruction at a time, keeping track of where it is in the source.
+pub struct Debugger<'a> {
+    program: Vec<Command>,
+    source: Vec<&'a str>,
+    input: Vec<u8>,
+    input_index: usize,
+    state: [Int; 4],
+    next: usize,
+}
+
+/// The state of the registers after running an instruction.
+pub struct TraceStep<'a> {
+    pub index: usize,
+    pub source: &'a str,
+    pub state: [Int; 4],
+}
+
+impl Display for TraceStep<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:>4}  {:<12} {}", self.index, self.source, Registers(&self.state))
+    }
+}
+
+struct Registers<'a>(&'a [Int; 4]);
+
+impl Display for Registers<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let [w, x, y, z] = self.0;
+        write!(f, "w={} x={} y={} z={}", w, x, y, z)
+    }
+}
+
+impl<'a> Debugger<'a> {
+    pub fn new(program: &'a str, input: &[u8]) -> Result<Debugger<'a>, String> {
+        Ok(Debugger {
+            program: parse_program(program)?,
+            source: source_lines(program),
+            input: input.to_vec(),
+            input_index: 0,
+            state: [0; 4],
+            next: 0,
+        })
+    }
+
+    pub fn state(&self) -> [Int; 4] {
+        self.state
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.next == self.program.len()
+    }
+
+    /// Whether the next instruction reads an input.
+    pub fn at_input(&self) -> bool {
+        matches!(self.program.get(self.next), Some(Command::Inp(_)))
+    }
+
+    /// Runs the next instruction, if there is one.
+    pub fn step(&mut self) -> Option<Result<TraceStep<'a>, String>> {
+        let index = self.next;
+        let command = self.program.get(index)?;
+
+        if let Err(reason) = execute(command, &mut self.state, &self.input, &mut self.input_index) {
+            return Some(Err(format!("Instruction {} ({}): {}", index, self.source[index], reason)));
+        }
+        self.next += 1;
+
+        Some(Ok(TraceStep { index, source: self.source[index], state: self.state }))
+    }
+}
+
+/// Runs the program on the digits in `input`, printing the registers after every instruction.
+///
+/// With `breakpoints`, it stops before every `inp` instead and reads a command: `s` to step through
+/// instructions one at a time, `c` (or nothing) to continue to the next `inp` and `q` to quit.
+pub fn debug<R: BufRead, W: Write>(program: &str, input: &str, breakpoints: bool, reader: R,
+                                   mut writer: W) -> Result<[Int; 4], String> {
+    let input: Vec<u8> = input.trim().chars()
+        .map(|c| c.to_digit(10).map(|d| d as u8))
+        .collect::<Option<_>>()
+        .ok_or(format!("The input should be digits, not '{}'", input))?;
+
+    let mut debugger = Debugger::new(program, &input)?;
+    let mut lines = reader.lines();
+    let mut breakpoints = breakpoints;
+    let mut stepping = false;
+    let io_error = |err: std::io::Error| err.to_string();
+
+    while !debugger.is_finished() {
+        if breakpoints && (stepping || debugger.at_input()) {
+            let next = debugger.next;
+            write!(writer, "break before {} ({}) {}\n> ", next, debugger.source[next],
+                   Registers(&debugger.state)).map_err(io_error)?;
+            writer.flush().map_err(io_error)?;
+
+            match lines.next().transpose().map_err(io_error)? {
+                Some(line) => match line.trim() {
+                    "s" => stepping = true,
+                    "c" | "" => stepping = false,
+                    "q" => return Ok(debugger.state),
+                    other => {
+                        writeln!(writer, "Unknown command '{}', expected s, c or q", other)
+                            .map_err(io_error)?;
+                        continue;
+                    }
+                },
+                // Nothing more to read, so run to the end.
+                None => breakpoints = false,
+            }
+        }
+
+        let step = debugger.step().unwrap()?;
+        if !breakpoints || stepping {
+            writeln!(writer, "{}", step).map_err(io_error)?;
+        }
+    }
+
+    writeln!(writer, "finished {}", Registers(&debugger.state)).map_err(io_error)?;
+    Ok(debugger.state)
+}
+
+/// A register's value as an expression of the inputs, which are digits from 1 to 9. Every
+/// expression knows the range of values it can take, which is what lets comparisons between
+/// inputs and values they can never be equal to simplify to 0.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Expr(Rc<Node>);
+
+#[derive(Debug, PartialEq, Eq)]
+struct Node {
+    kind: ExprKind,
+    min: Int,
+    max: Int,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum ExprKind {
+    Constant(Int),
+    Input(usize),
+    Add(Expr, Expr),
+    Mul(Expr, Expr),
+    Div(Expr, Expr),
+    Mod(Expr, Expr),
+    Eql(Expr, Expr),
+}
+
+impl Expr {
+    fn new(kind: ExprKind, min: Int, max: Int) -> Expr {
+        Expr(Rc::new(Node { kind, min, max }))
+    }
+
+    pub fn constant(value: Int) -> Expr {
+        Expr::new(ExprKind::Constant(value), value, value)
+    }
+
+    pub fn input(n: usize) -> Expr {
+        Expr::new(ExprKind::Input(n), 1, 9)
+    }
+
+    fn as_constant(&self) -> Option<Int> {
+        match self.0.kind {
+            ExprKind::Constant(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    fn is_within(&self, min: Int, max: Int) -> bool {
+        min <= self.0.min && self.0.max <= max
+    }
+
+    pub fn add(a: &Expr, b: &Expr) -> Expr {
+        match (a.as_constant(), b.as_constant()) {
+            (Some(x), Some(y)) if x.checked_add(y).is_some() => return Expr::constant(x + y),
+            (Some(0), _) => return b.clone(),
+            (_, Some(0)) => return a.clone(),
+            // Keep constants on the right, and gather them up.
+            (Some(_), None) => return Expr::add(b, a),
+            (None, Some(c)) => {
+                if let ExprKind::Add(inner, other) = &a.0.kind {
+                    if let Some(sum) = other.as_constant().and_then(|d| c.checked_add(d)) {
+                        return Expr::add(inner, &Expr::constant(sum));
+                    }
+                }
+            }
+            _ => {}
+        }
+
+        let min = a.0.min.saturating_add(b.0.min);
+        let max = a.0.max.saturating_add(b.0.max);
+        Expr::new(ExprKind::Add(a.clone(), b.clone()), min, max)
+    }
+
+    pub fn mul(a: &Expr, b: &Expr) -> Expr {
+        match (a.as_constant(), b.as_constant()) {
+            (Some(x), Some(y)) if x.checked_mul(y).is_some() => return Expr::constant(x * y),
+            (Some(0), _) | (_, Some(0)) => return Expr::constant(0),
+            (Some(1), _) => return b.clone(),
+            (_, Some(1)) => return a.clone(),
+            (Some(_), None) => return Expr::mul(b, a),
+            _ => {}
+        }
+
+        let products = [
+            a.0.min.saturating_mul(b.0.min), a.0.min.saturating_mul(b.0.max),
+            a.0.max.saturating_mul(b.0.min), a.0.max.saturating_mul(b.0.max),
+        ];
+        let min = *products.iter().min().unwrap();
+        let max = *products.iter().max().unwrap();
+        Expr::new(ExprKind::Mul(a.clone(), b.clone()), min, max)
+    }
+
+    /// If this is `q * k + r` with `r` between 0 and k - 1, returns `q` and `r`.
+    fn split_multiple(&self, k: Int) -> Option<(Expr, Expr)> {
+        if let ExprKind::Add(left, right) = &self.0.kind {
+            if let ExprKind::Mul(q, multiple) = &left.0.kind {
+                if multiple.as_constant() == Some(k) && q.0.min >= 0 && right.is_within(0, k - 1) {
+                    return Some((q.clone(), right.clone()));
+                }
+            }
+        }
+        if let ExprKind::Mul(q, multiple) = &self.0.kind {
+            if multiple.as_constant() == Some(k) && q.0.min >= 0 {
+                return Some((q.clone(), Expr::constant(0)));
+            }
+        }
+        None
+    }
+
+    pub fn div(a: &Expr, b: &Expr) -> Expr {
+        if let Some(k) = b.as_constant().filter(|k| *k > 0) {
+            if let Some(a) = a.as_constant() {
+                return Expr::constant(a / k);
+            }
+            if k == 1 {
+                return a.clone();
+            }
+            if a.is_within(0, k - 1) {
+                return Expr::constant(0);
+            }
+            if let Some((q, _)) = a.split_multiple(k) {
+                return q;
+            }
+            let (min, max) = (a.0.min / k, a.0.max / k);
+            return Expr::new(ExprKind::Div(a.clone(), b.clone()), min, max);
+        }
+
+        Expr::new(ExprKind::Div(a.clone(), b.clone()), Int::MIN, Int::MAX)
+    }
+
+    pub fn modulo(a: &Expr, b: &Expr) -> Expr {
+        if let Some(k) = b.as_constant().filter(|k| *k > 0) {
+            if let Some(a) = a.as_constant().filter(|a| *a >= 0) {
+                return Expr::constant(a % k);
+            }
+            if a.is_within(0, k - 1) {
+                return a.clone();
+            }
+            if let Some((_, r)) = a.split_multiple(k) {
+                return r;
+            }
+            return Expr::new(ExprKind::Mod(a.clone(), b.clone()), 0, k - 1);
+        }
+
+        Expr::new(ExprKind::Mod(a.clone(), b.clone()), 0, b.0.max.max(0))
+    }
+
+    pub fn eql(a: &Expr, b: &Expr) -> Expr {
+        if let (Some(a), Some(b)) = (a.as_constant(), b.as_constant()) {
+            return Expr::constant((a == b) as Int);
+        }
+        if a.0.max < b.0.min || b.0.max < a.0.min {
+            return Expr::constant(0);
+        }
+        if a == b {
+            return Expr::constant(1);
+        }
+
+        Expr::new(ExprKind::Eql(a.clone(), b.clone()), 0, 1)
+    }
+
+    /// Formats the expression, cutting it off after `limit` characters; the expressions for `z`
+    /// grow to tens of thousands of characters by the end of MONAD.
+    pub fn to_short_string(&self, limit: usize) -> String {
+        let text = self.to_string();
+        match text.char_indices().nth(limit) {
+            Some((end, _)) => format!("{} ... ({} characters)", text[..end].trim_end(), text.len()),
+            None => text,
+        }
+    }
+
+    /// How tightly the expression binds, for deciding where brackets go.
+    fn precedence(&self) -> u8 {
+        match self.0.kind {
+            ExprKind::Add(_, _) => 1,
+            ExprKind::Mul(_, _) | ExprKind::Div(_, _) | ExprKind::Mod(_, _) => 2,
+            _ => 3,
+        }
+    }
+
+    fn fmt_operand(&self, f: &mut Formatter<'_>, precedence: u8) -> std::fmt::Result {
+        if self.precedence() < precedence {
+            write!(f, "({})", self)
+        } else {
+            write!(f, "{}", self)
+        }
+    }
+}
+
+impl Display for Expr {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let (a, b, op) = match &self.0.kind {
+            ExprKind::Constant(value) => return write!(f, "{}", value),
+            ExprKind::Input(n) => return write!(f, "w{}", n),
+            ExprKind::Eql(a, b) => return write!(f, "eql({}, {})", a, b),
+            ExprKind::Add(a, b) => {
+                if let Some(c) = b.as_constant().filter(|c| *c < 0) {
+                    a.fmt_operand(f, 1)?;
+                    return write!(f, " - {}", -c);
+                }
+                (a, b, "+")
+            }
+            ExprKind::Mul(a, b) => (a, b, "*"),
+            ExprKind::Div(a, b) => (a, b, "/"),
+            ExprKind::Mod(a, b) => (a, b, "%"),
+        };
+
+        // Everything is left associative, so the right operand needs brackets if it binds the
+        // same as this does.
+        let precedence = self.precedence();
+        a.fmt_operand(f, precedence)?;
+        write!(f, " {} ", op)?;
+        b.fmt_operand(f, precedence + 1)
+    }
+}
+
+/// The expression each instruction assigns to its register, along with the instruction, and the
+/// final registers.
+pub struct SymbolicTrace {
+    pub steps: Vec<(String, Expr)>,
+    pub registers: [Expr; 4],
+}
+
+/// Runs the program with every input unknown.
+pub fn symbolic_trace(program: &str) -> Result<SymbolicTrace, String> {
+    let source = source_lines(program);
+    let program = parse_program(program)?;
+
+    let mut registers: [Expr; 4] = std::array::from_fn(|_| Expr::constant(0));
+    let mut inputs = 0;
+    let mut steps = Vec::new();
+
+    for (index, command) in program.iter().enumerate() {
+        let operand = |o: &VarOrLiteral, registers: &[Expr; 4]| match o {
+            VarOrLiteral::Variable(v) => registers[v.index()].clone(),
+            VarOrLiteral::Literal(value) => Expr::constant(*value),
+        };
+
+        let (v, value) = match command {
+            Command::Inp(v) => {
+                inputs += 1;
+                (v, Expr::input(inputs - 1))
+            }
+            Command::Add(v, o) => (v, Expr::add(&registers[v.index()], &operand(o, &registers))),
+            Command::Mul(v, o) => (v, Expr::mul(&registers[v.index()], &operand(o, &registers))),
+            Command::Div(v, o) => (v, Expr::div(&registers[v.index()], &operand(o, &registers))),
+            Command::Mod(v, o) => (v, Expr::modulo(&registers[v.index()], &operand(o, &registers))),
+            Command::Eql(v, o) => (v, Expr::eql(&registers[v.index()], &operand(o, &registers))),
+        };
+
+        let register = "wxyz".as_bytes()[v.index()] as char;
+        let line = format!("{:>4}  {:<12} {} =", index, source[index], register);
+        registers[v.index()] = value.clone();
+        steps.push((line, value));
+    }
+
+    Ok(SymbolicTrace { steps, registers })
 }
 
 #[cfg(test)]
@@ -213,9 +1125,9 @@ mod test {
     fn negate_example() {
         let program = "inp x\nmul x -1";
 
-        assert_eq!(-1, parse_and_run_program(program, &[1])[get_index('x')]);
-        assert_eq!(-24, parse_and_run_program(program, &[24])[get_index('x')]);
-        assert_eq!(0, parse_and_run_program(program, &[0])[get_index('x')]);
+        assert_eq!(-1, parse_and_run_program(program, &[1]).unwrap()[get_index('x')]);
+        assert_eq!(-24, parse_and_run_program(program, &[24]).unwrap()[get_index('x')]);
+        assert_eq!(0, parse_and_run_program(program, &[0]).unwrap()[get_index('x')]);
     }
 
     #[test]
@@ -226,9 +1138,9 @@ inp x
 mul z 3
 eql z x";
 
-        assert_eq!(1, parse_and_run_program(program, &[1, 3])[get_index('z')]);
-        assert_eq!(1, parse_and_run_program(program, &[2, 6])[get_index('z')]);
-        assert_eq!(0, parse_and_run_program(program, &[1, 2])[get_index('z')]);
+        assert_eq!(1, parse_and_run_program(program, &[1, 3]).unwrap()[get_index('z')]);
+        assert_eq!(1, parse_and_run_program(program, &[2, 6]).unwrap()[get_index('z')]);
+        assert_eq!(0, parse_and_run_program(program, &[1, 2]).unwrap()[get_index('z')]);
     }
 
     #[test]
@@ -246,11 +1158,11 @@ mod x 2
 div w 2
 mod w 2";
 
-        assert_eq!([0, 1, 1, 0], parse_and_run_program(program, &[6]));
-        assert_eq!([0, 1, 0, 1], parse_and_run_program(program, &[37]));
-        assert_eq!([1, 1, 1, 1], parse_and_run_program(program, &[15]));
-        assert_eq!([0, 0, 0, 0], parse_and_run_program(program, &[0]));
-        assert_eq!([0, 0, 0, 0], parse_and_run_program(program, &[32]));
+        assert_eq!([0, 1, 1, 0], parse_and_run_program(program, &[6]).unwrap());
+        assert_eq!([0, 1, 0, 1], parse_and_run_program(program, &[37]).unwrap());
+        assert_eq!([1, 1, 1, 1], parse_and_run_program(program, &[15]).unwrap());
+        assert_eq!([0, 0, 0, 0], parse_and_run_program(program, &[0]).unwrap());
+        assert_eq!([0, 0, 0, 0], parse_and_run_program(program, &[32]).unwrap());
     }
 
     #[test]
@@ -261,6 +1173,292 @@ mod w 2";
         );
     }
 
+    #[test]
+    fn model_numbers() {
+        let input = std::fs::read_to_string("input/input-47.txt").unwrap();
+        let best = search_model_numbers(&input).unwrap();
+        assert_eq!(91297395919993, best.largest);
+        assert_eq!(71131151917891, best.smallest);
+
+        for number in [best.largest, best.smallest] {
+            assert_eq!(0, parse_and_run_program(&input, &num_to_digits(number)).unwrap()[3]);
+        }
+
+        assert!(search_model_numbers("inp w\nadd z w").is_err());
+        assert!(search_model_numbers("add z 1\ninp w").is_err());
+        assert_eq!(Err(String::from("The program has no inp instructions")),
+                   search_model_numbers(""));
+
+        // Every MONAD block only shrinks z by dividing it, so the search can limit z.
+        for block in input.split("inp w").skip(1) {
+            assert!(only_divides_z(&parse_program(&format!("inp w{}", block)).unwrap()));
+        }
+        assert!(!only_divides_z(&parse_program("inp w\nadd x w\nadd x -2\nadd z x").unwrap()));
+        assert!(!only_divides_z(&parse_program("inp w\nmod z 26").unwrap()));
+
+        // Here the second part gets z back to 0 without dividing.
+        let program = "inp w\nadd z w\nmul z 100\ninp w\nmul z 0\nadd z w\nadd z -1";
+        assert_eq!(Ok(ModelNumbers { largest: 91, smallest: 11 }), search_model_numbers(program));
+        assert!(search_model_numbers("  \n\n").is_err());
+
+        assert_eq!(Ok(best), analyse(&input));
+    }
+
+    #[test]
+    fn analysis() {
+        let input = std::fs::read_to_string("input/input-47.txt").unwrap();
+
+        let blocks = parse_blocks(&input).unwrap();
+        assert_eq!(14, blocks.len());
+        assert_eq!(Block { div_z: 1, add_x: 14, add_y: 0 }, blocks[0]);
+        assert_eq!(Block { div_z: 26, add_x: -6, add_y: 12 }, blocks[13]);
+
+        let constraints = constraints(&blocks).unwrap();
+        assert_eq!(7, constraints.len());
+        // Block 3 pushes w + 0 and block 4 pops it with add x -2.
+        assert!(constraints.contains(&Constraint { push: 3, pop: 4, offset: -2 }));
+        assert!(constraints.contains(&Constraint { push: 0, pop: 13, offset: -6 }));
+
+        assert_eq!(Ok(91297395919993), part1(&input));
+        assert_eq!(Ok(71131151917891), part2(&input));
+    }
+
+    #[test]
+    fn analysis_errors() {
+        let input = std::fs::read_to_string("input/input-47.txt").unwrap();
+
+        assert!(analyse("inp w\nadd z w").is_err());
+
+        // Instruction 3 of the first block is "mod x 26".
+        let err = analyse(&input.replacen("mod x 26", "mod x 25", 1)).unwrap_err();
+        assert!(err.contains("Instruction 3"), "{}", err);
+        assert!(analyse(&input.replacen("add x 14", "add x abc", 1)).is_err());
+        assert!(analyse(&input.replacen("div z 1", "div z 2", 1)).is_err());
+        // A first block that doesn't always push.
+        assert!(analyse(&input.replacen("add x 14", "add x 5", 1)).is_err());
+        // Pushes and pops that don't pair up.
+        assert!(analyse(&input.replacen("div z 26", "div z 1", 1)
+            .replacen("add x -2", "add x 12", 1)).is_err());
+        assert!(analyse(&input.replacen("div z 1", "div z 26", 1)).is_err());
+    }
+
+    /// Generates a random program using xorshift, so the programs are the same on every run. Only
+    /// multiplying by small literals means values at most double each instruction, so they don't
+    /// overflow.
+    fn random_program(seed: u64, length: usize) -> String {
+        let mut state = seed;
+        let mut next = |range: u64| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state % range
+        };
+
+        let mut program = String::new();
+        for _ in 0..length {
+            let a = ["w", "x", "y", "z"][next(4) as usize];
+            let register = ["w", "x", "y", "z"][next(4) as usize];
+            let b = match next(3) {
+                0 => register.to_string(),
+                _ => (next(7) as i32 - 2).to_string(),
+            };
+            let line = match next(7) {
+                0 => format!("inp {}", a),
+                1 | 2 => format!("add {} {}", a, b),
+                3 => format!("mul {} {}", a, next(5) as i32 - 2),
+                4 | 5 => {
+                    // Mostly divide by positive numbers, so not too many programs are invalid.
+                    let divisor = match next(4) {
+                        0 => register.to_string(),
+                        1 => (next(3) as i32 - 1).to_string(),
+                        _ => (next(5) + 1).to_string(),
+                    };
+                    let command = if next(2) == 0 { "div" } else { "mod" };
+                    format!("{} {} {}", command, a, divisor)
+                }
+                _ => format!("eql {} {}", a, b),
+            };
+            program.push_str(&line);
+            program.push('\n');
+        }
+        program
+    }
+
+    #[test]
+    fn compiled_matches_interpreter() {
+        let digits = [3, 1, 4, 1, 5, 9, 2, 6, 5, 3, 5, 8, 9, 7, 9, 3, 2, 3, 8, 4, 6, 2, 6, 4, 3];
+        let mut errors = 0;
+
+        for seed in 1..2000 {
+            let program = parse_program(&random_program(seed, 25)).unwrap();
+            // Sometimes run out of input.
+            let input = if seed % 4 == 0 { &digits[..(seed as usize % 5)] } else { &digits };
+            let start = [seed as Int % 5, 2, -3, 7];
+            let expected = run_program_with_state(&program, input, start);
+            errors += expected.is_err() as usize;
+
+            let compiled = Compiled::new(&program, [None; 4], [true; 4]);
+            assert_eq!(expected, compiled.run(input, start), "seed {}", seed);
+
+            // Knowing some registers up front, and only wanting some of them at the end.
+            let known = [Some(start[0]), None, Some(start[2]), None];
+            let compiled = Compiled::new(&program, known, [false, true, false, true]);
+            let result = compiled.run(input, start);
+            assert_eq!(expected.clone().map(|state| (state[1], state[3])),
+                       result.map(|state| (state[1], state[3])), "seed {}", seed);
+
+            // Including the known registers in what's wanted at the end.
+            let compiled = Compiled::new(&program, known, [true; 4]);
+            assert_eq!(expected, compiled.run(input, start), "seed {}", seed);
+            let compiled = Compiled::new(&program, start.map(Some), [true; 4]);
+            assert_eq!(expected, compiled.run(input, start), "seed {}", seed);
+        }
+
+        // Check the invalid cases are covered too.
+        assert!((200..1800).contains(&errors), "{} programs failed", errors);
+    }
+
+    #[test]
+    fn alu_errors() {
+        let run = |program: &str, input: &[u8]| parse_and_run_program(program, input);
+
+        assert_eq!(Err(String::from("Instruction 2: division by zero")),
+                   run("inp w\nadd x 3\ndiv x y", &[1]));
+        assert_eq!(Err(String::from("Instruction 1: modulo of a negative number")),
+                   run("add x -7\nmod x 2", &[]));
+        assert_eq!(Err(String::from("Instruction 1: modulo by a number that isn't positive")),
+                   run("add x 7\nmod x -2", &[]));
+        assert_eq!(Err(String::from("Instruction 1: ran out of input")),
+                   run("inp w\ninp x", &[1]));
+
+        assert_eq!(Err(String::from("Instruction 2: overflow")),
+                   run("add x 9223372036854775807\ninp w\nadd x w", &[1]));
+        assert_eq!(Err(String::from("Instruction 2: overflow")),
+                   run("add x -4611686018427387905\nadd y 2\nmul x y", &[]));
+        assert_eq!(Err(String::from("Instruction 2: overflow")),
+                   run("add x -9223372036854775807\nadd x -1\ndiv x -1", &[]));
+
+        // Large literals and values are fine.
+        assert_eq!(Ok([0, 0, 0, 26 * 26 * 26 * 26 * 26 * 26 * 26 * 26]),
+                   run("add z 208827064576", &[]));
+        assert_eq!(Ok([0, -6000000000, 0, 0]), run("add x -3000000000\nmul x 2", &[]));
+
+        assert!(parse_program("add x").is_err());
+        assert!(parse_program("inp w x").is_err());
+        assert!(parse_program("sub x 1").is_err());
+        assert!(parse_program("add a 1").is_err());
+        assert!(parse_program("add x 1.5").is_err());
+        assert_eq!(Err(String::from("Could not parse line 2: 'add x 99999999999999999999'")),
+                   parse_program("inp w\nadd x 99999999999999999999"));
+    }
+
+    #[test]
+    fn compiler_optimisations() {
+        let compile = |program: &str, known, outputs| {
+            Compiled::new(&parse_program(program).unwrap(), known, outputs).ops
+        };
+        let (w, x, y, z) = (0, 1, 2, 3);
+        use Operand::{Constant, Register};
+
+        // The MONAD idiom of resetting a register and then copying another into it.
+        assert_eq!(vec![Op::Set(x, Register(z))],
+                   compile("mul x 0\nadd x z", [None; 4], [false, true, false, false]));
+
+        // Constants are folded and registers that end up constant are only set once.
+        assert_eq!(vec![Op::Set(y, Constant(26))],
+                   compile("mul y 0\nadd y 25\nadd y 1\ndiv z 1", [None; 4], [true; 4]));
+        assert_eq!(Vec::<Op>::new(),
+                   compile("mul y 0\nadd y 25\nadd y 1", [None, None, Some(26), None], [true; 4]));
+
+        // A known register that's overwritten and then folds back to its starting value still
+        // needs setting back.
+        for program in ["inp w\nadd x w\nmul x 0", "inp x\nmul x 0"] {
+            let compiled = Compiled::new(&parse_program(program).unwrap(), [Some(0); 4], [true; 4]);
+            assert_eq!(Some(&Op::Set(x, Constant(0))), compiled.ops.last(), "{}", program);
+            assert_eq!(Ok(0), compiled.run(&[7], [0; 4]).map(|state| state[x]), "{}", program);
+        }
+
+        // Dead stores go, but inputs, possible division by zero and possible overflow stay.
+        assert_eq!(vec![Op::Inp(w), Op::Add(y, Register(y), Register(w)),
+                        Op::Div(x, Register(x), Register(y))],
+                   compile("inp w\nadd y w\ndiv x y\nmul x 0\neql y 1",
+                           [None; 4], [false, false, false, true]));
+
+        let program = parse_program("add z w\ninp w\nmul x 0\nadd x y\nmul x z").unwrap();
+        let compiled = Compiled::new(&program, [None; 4], [true; 4]);
+        assert_eq!([true, false, true, true], compiled.reads);
+    }
+
+    #[test]
+    fn trace() {
+        let program = "inp z\ninp x\nmul z 3\neql z x";
+        let run = |input: &str, commands: &str| {
+            let mut output = Vec::new();
+            let breakpoints = !commands.is_empty();
+            let state = debug(program, input, breakpoints, commands.as_bytes(), &mut output);
+            (state, String::from_utf8(output).unwrap())
+        };
+
+        assert_eq!((Ok([0, 3, 0, 1]), String::from(r"   0  inp z        w=0 x=0 y=0 z=1
+   1  inp x        w=0 x=3 y=0 z=1
+   2  mul z 3      w=0 x=3 y=0 z=3
+   3  eql z x      w=0 x=3 y=0 z=1
+finished w=0 x=3 y=0 z=1
+")), run("13", ""));
+
+        // Continue to the second inp, then step through the rest.
+        assert_eq!((Ok([0, 2, 0, 0]), String::from(r"break before 0 (inp z) w=0 x=0 y=0 z=0
+> break before 1 (inp x) w=0 x=0 y=0 z=1
+>    1  inp x        w=0 x=2 y=0 z=1
+break before 2 (mul z 3) w=0 x=2 y=0 z=1
+> Unknown command 'n', expected s, c or q
+break before 2 (mul z 3) w=0 x=2 y=0 z=1
+>    2  mul z 3      w=0 x=2 y=0 z=3
+break before 3 (eql z x) w=0 x=2 y=0 z=3
+>    3  eql z x      w=0 x=2 y=0 z=0
+finished w=0 x=2 y=0 z=0
+")), run("12", "c\ns\nn\ns\ns\n"));
+
+        // Quitting stops where it is, and running out of commands runs to the end.
+        assert_eq!(Ok([0, 0, 0, 1]), run("12", "\nq\n").0);
+        assert_eq!(Ok([0, 2, 0, 0]), run("12", "s\n").0);
+
+        assert_eq!(Err(String::from("Instruction 1 (inp x): ran out of input")), run("1", "").0);
+        assert!(run("1x", "").0.is_err());
+    }
+
+    #[test]
+    fn symbolic() {
+        let input = std::fs::read_to_string("input/input-47.txt").unwrap();
+        let SymbolicTrace { steps, registers } = symbolic_trace(&input).unwrap();
+
+        assert_eq!("   0  inp w        w =", steps[0].0);
+        assert_eq!("w0", steps[0].1.to_string());
+        assert_eq!("0", steps[6].1.to_string());
+        assert_eq!("w0", steps[17].1.to_string());
+        assert_eq!("w0 * 26 + (w1 + 12)", steps[35].1.to_string());
+        // The x for a popping block compares the input to the digit pushed before it.
+        assert_eq!("eql(eql(w3 - 2, w4), 0)", steps[4 * 18 + 7].1.to_string());
+        assert_eq!("w13", registers[0].to_string());
+
+        let program = "inp x\nmul x 26\nadd x 5\ninp y\nadd y -2\nmod x 26\nadd x y\ndiv x 2";
+        let registers = symbolic_trace(program).unwrap().registers;
+        assert_eq!("(w1 + 3) / 2", registers[1].to_string());
+        assert_eq!("w1 - 2", registers[2].to_string());
+        assert_eq!("w1 - 2", registers[2].to_short_string(6));
+        assert_eq!("(w1 + ... (12 characters)", registers[1].to_short_string(6));
+
+        let program = "inp w\neql w 10\ninp x\neql x x\ninp y\nmod y y\ninp z\nadd z -1\nmul z 3";
+        let registers = symbolic_trace(program).unwrap().registers;
+        assert_eq!(["0", "1", "w2 % w2", "(w3 - 1) * 3"], registers.map(|r| r.to_string()));
+
+        // Constants that would overflow are left as they are.
+        let program = "add x 9223372036854775807\nadd x 1\nadd y 4611686018427387904\nmul y 2";
+        let registers = symbolic_trace(program).unwrap().registers;
+        assert_eq!("9223372036854775807 + 1", registers[1].to_string());
+        assert_eq!("4611686018427387904 * 2", registers[2].to_string());
+    }
+
     #[test]
     fn dev() {
         let program = "\
@@ -273,23 +1471,24 @@ add x 14
 eql x w
 eql x 0";
 
-        let program = parse_program(program);
+        let program = parse_program(program).unwrap();
         for z in 0..100 {
             for w in 1..10 {
-                let z : i32 = z - 50;  // Make sure we work with negative numbers as well.
-                let x = if (w as i32) == z % 26 + 14 { 0 } else { 1 };
-                assert_eq!(x, run_program_with_state(&program, &[w], [0, 0, 0, z.into()])[1]);
+                let x = if (w as Int) == z % 26 + 14 { 0 } else { 1 };
+                assert_eq!(x, run_program_with_state(&program, &[w], [0, 0, 0, z]).unwrap()[1]);
             }
         }
+        // Negative numbers can't be used with mod.
+        assert!(run_program_with_state(&program, &[1], [0, 0, 0, -1]).is_err());
 
         let program = "\
 mul y 0
 add y 25
 mul y x
 add y 1";
-        let program = parse_program(program);
+        let program = parse_program(program).unwrap();
         for x in 0..2 {
-            assert_eq!(25 * x + 1, run_program_with_state(&program, &[], [0, x, 0, 0])[2]);
+            assert_eq!(25 * x + 1, run_program_with_state(&program, &[], [0, x, 0, 0]).unwrap()[2]);
         }
 
         let program = "\
@@ -297,12 +1496,13 @@ mul y 0
 add y w
 add y 12
 mul y x";
-        let program = parse_program(program);
+        let program = parse_program(program).unwrap();
         for w in 1..10 {
             for x in 0..50 {
                 let x = x - 50;  // Make sure we work with negative numbers as well.
-                assert_eq!((w + 12) * x, run_program_with_state(&program, &[], [w, x, 0, 0])[2]);
+                let state = run_program_with_state(&program, &[], [w, x, 0, 0]).unwrap();
+                assert_eq!((w + 12) * x, state[2]);
             }
         }
     }
-}
\ No newline at end of file
+}
@@ -1,11 +1,44 @@
 use std::fmt::{Debug, Formatter, Write};
+use std::io;
+use std::path::Path;
+use crate::netpbm;
 
+const WORD_BITS: usize = u64::BITS as usize;
+
+/// The herds as bitboards: each row is `words` words with one bit per cell, and each herd has its
+/// own set of rows.
 struct Map {
-    contents: Vec<Vec<char>>,
+    east: Vec<u64>,
+    south: Vec<u64>,
+    /// Scratch space for the cucumbers that are about to move.
+    moving: Vec<u64>,
+    words: usize,
     width: usize,
     height: usize
 }
 
+/// How many sea cucumbers in each herd moved in a step.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct Moves {
+    pub east: u32,
+    pub south: u32,
+}
+
+impl Moves {
+    pub fn any(&self) -> bool {
+        self.east + self.south > 0
+    }
+}
+
+/// How `dump_steps` writes each frame.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum FrameFormat {
+    /// The map as it appears in the puzzle.
+    Text,
+    /// A plain PGM image with the east-facing herd grey and the south-facing herd black.
+    Pgm,
+}
+
 #[derive(Debug, Eq, PartialEq)]
 enum Direction {
     East,
@@ -13,75 +46,155 @@ enum Direction {
 }
 
 impl Map {
-    fn parse(input: &str) -> Map {
-        let contents : Vec<Vec<char>> = input.lines()
-            .map(|line| line.chars().collect())
-            .collect();
-        Map {
-            height: contents.len(),
-            width: contents.first().unwrap().len(),
-            contents
+    fn parse(input: &str) -> Result<Map, String> {
+        let lines: Vec<&str> = input.lines().collect();
+        let width = lines.first().map_or(0, |line| line.len());
+        if width == 0 {
+            return Err(String::from("The map has no cells"));
         }
-    }
+        let words = width.div_ceil(WORD_BITS);
+        let mut map = Map {
+            east: vec![0; words * lines.len()],
+            south: vec![0; words * lines.len()],
+            moving: vec![0; words * lines.len()],
+            words,
+            width,
+            height: lines.len(),
+        };
 
-    fn step(&self) -> Option<Map> {
-        if let Some(moved_east) = self.step_dir(&Direction::East) {
-            // TODO: There's probably a nicer way to do this.
-            if let Some(moved_south) = moved_east.step_dir(&Direction::South) {
-                println!("Moved east and south");
-                Some(moved_south)
-            } else {
-                println!("Moved east");
-                Some(moved_east)
+        for (y, line) in lines.iter().enumerate() {
+            if line.len() != width {
+                return Err(format!("Row {} has length {}, expected {}", y, line.len(), width));
+            }
+
+            for (x, c) in line.chars().enumerate() {
+                let herd = match c {
+                    '>' => &mut map.east,
+                    'v' => &mut map.south,
+                    '.' => continue,
+                    other => return Err(format!("Unexpected cell '{}' in row {}", other, y)),
+                };
+                herd[y * words + x / WORD_BITS] |= 1 << (x % WORD_BITS);
             }
-        } else {
-            println!("Moved south");
-            self.step_dir(&Direction::South)
         }
+
+        Ok(map)
     }
 
-    fn step_dir(&self, dir: &Direction) -> Option<Map> {
-        let mut new_contents = vec![vec!['.'; self.width]; self.height];
-        let mut changed = false;
+    fn step(&mut self) -> Moves {
+        let east = self.move_herd(&Direction::East);
+        let south = self.move_herd(&Direction::South);
+        Moves { east, south }
+    }
 
-        for y in 0..self.height {
-            for x in 0..self.width {
-                let (next_x, next_y) = match dir {
-                    Direction::East => ((x + 1) % self.width, y),
-                    Direction::South => (x, (y + 1) % self.height),
-                };
+    /// Moves every cucumber in the herd that has space in front of it, returning how many moved.
+    fn move_herd(&mut self, dir: &Direction) -> u32 {
+        match dir {
+            Direction::East => self.move_east(),
+            Direction::South => self.move_south(),
+        }
 
-                let c = match dir {
-                    Direction::East => '>',
-                    Direction::South => 'v',
+        self.moving.iter().map(|word| word.count_ones()).sum()
+    }
+
+    fn move_east(&mut self) {
+        let words = self.words;
+        // Where the last cell of a row is in its word, and the bits of that word that are cells.
+        let last_bit = (self.width - 1) % WORD_BITS;
+        let last_mask = u64::MAX >> (WORD_BITS - 1 - last_bit);
+
+        for row in (0..self.height).map(|y| y * words) {
+            let (east, south) = (&self.east[row..row + words], &self.south[row..row + words]);
+            let occupied = |i: usize| east[i] | south[i];
+
+            for (i, moving) in self.moving[row..row + words].iter_mut().enumerate() {
+                // The cell to the east of each bit, wrapping round from the end of the row.
+                let next = if i + 1 < words {
+                    (occupied(i) >> 1) | (occupied(i + 1) << (WORD_BITS - 1))
+                } else {
+                    (occupied(i) >> 1) | ((occupied(0) & 1) << last_bit)
                 };
+                *moving = east[i] & !next;
+            }
 
-                if self.contents[y][x] == c && self.contents[next_y][next_x] == '.' {
-                    changed = true;
-                    new_contents[next_y][next_x] = self.contents[y][x];
-                } else if self.contents[y][x] != '.' {
-                    new_contents[y][x] = self.contents[y][x];
+            for i in 0..words {
+                let moving = self.moving[row + i];
+                let carry = if i > 0 {
+                    self.moving[row + i - 1] >> (WORD_BITS - 1)
+                } else {
+                    (self.moving[row + words - 1] >> last_bit) & 1
+                };
+                let mut east = (self.east[row + i] & !moving) | (moving << 1) | carry;
+                if i + 1 == words {
+                    east &= last_mask;
                 }
+                self.east[row + i] = east;
             }
         }
+    }
+
+    fn move_south(&mut self) {
+        let words = self.words;
+        let size = words * self.height;
+
+        for index in 0..size {
+            let below = (index + words) % size;
+            self.moving[index] = self.south[index] & !(self.east[below] | self.south[below]);
+        }
 
-        if changed {
-            Some(Map {
-                contents: new_contents,
-                width: self.width,
-                height: self.height,
-            })
+        for index in 0..size {
+            let above = (index + size - words) % size;
+            self.south[index] = (self.south[index] & !self.moving[index]) | self.moving[above];
+        }
+    }
+
+    #[cfg(test)]
+    fn step_dir(&self, dir: &Direction) -> Option<Map> {
+        let mut map = self.clone();
+        if map.move_herd(dir) > 0 {
+            Some(map)
         } else {
             None
         }
     }
+
+    fn get(&self, x: usize, y: usize) -> char {
+        let index = y * self.words + x / WORD_BITS;
+        let bit = 1 << (x % WORD_BITS);
+
+        if self.east[index] & bit != 0 {
+            '>'
+        } else if self.south[index] & bit != 0 {
+            'v'
+        } else {
+            '.'
+        }
+    }
+}
+
+impl Clone for Map {
+    fn clone(&self) -> Map {
+        Map {
+            east: self.east.clone(),
+            south: self.south.clone(),
+            moving: vec![0; self.moving.len()],
+            ..*self
+        }
+    }
+}
+
+impl PartialEq for Map {
+    fn eq(&self, other: &Map) -> bool {
+        self.width == other.width && self.height == other.height
+            && self.east == other.east && self.south == other.south
+    }
 }
 
 impl Debug for Map {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        for line in self.contents.iter() {
-            for c in line.iter() {
-                f.write_char(*c)?;
+        for y in 0..self.height {
+            for x in 0..self.width {
+                f.write_char(self.get(x, y))?;
             }
             f.write_char('\n')?;
         }
@@ -90,38 +203,113 @@ impl Debug for Map {
     }
 }
 
-pub fn part1(input: &str) -> u32 {
-    let mut map = Map::parse(input);
-    let mut step_count = 1;
+/// Runs the herds until they stop, calling `frame` with the map before the first step and after
+/// every `every`th step, and after the last step. Returns what moved in each step, the last being
+/// the step where nothing did.
+fn simulate<F>(mut map: Map, every: usize, mut frame: F) -> io::Result<Vec<Moves>>
+    where F: FnMut(usize, &Map) -> io::Result<()> {
+    let mut moves = Vec::new();
+
+    frame(0, &map)?;
+    loop {
+        let step = map.step();
+        moves.push(step);
 
-    while let Some(next_map) = map.step() {
-        map = next_map;
-        step_count += 1;
+        if !step.any() || moves.len() % every.max(1) == 0 {
+            frame(moves.len(), &map)?;
+        }
+        if !step.any() {
+            return Ok(moves);
+        }
     }
+}
 
-    step_count
+pub fn move_counts(input: &str) -> Result<Vec<Moves>, String> {
+    Ok(simulate(Map::parse(input)?, usize::MAX, |_, _| Ok(())).unwrap())
+}
+
+/// Writes the map before the first step, after every `every`th step and once the herds have
+/// stopped to `dir`, along with a `moves.txt` with a `step east south` line for each step, counting
+/// how many of each herd moved.
+pub fn dump_steps(input: &str, every: usize, format: FrameFormat, dir: &Path) -> io::Result<()> {
+    let map = Map::parse(input).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+    let moves = simulate(map, every, |step, map| {
+        match format {
+            FrameFormat::Text => {
+                netpbm::write_to_dir(dir, &format!("step-{:03}.txt", step), &format!("{:?}", map))
+            }
+            FrameFormat::Pgm => {
+                let pgm = netpbm::to_pgm(map.width, map.height, 2, |x, y| match map.get(x, y) {
+                    '>' => 1,
+                    'v' => 0,
+                    _ => 2,
+                });
+                netpbm::write_to_dir(dir, &format!("step-{:03}.pgm", step), &pgm)
+            }
+        }
+    })?;
+
+    let report: String = moves.iter().enumerate()
+        .map(|(step, moves)| format!("{} {} {}\n", step + 1, moves.east, moves.south))
+        .collect();
+    netpbm::write_to_dir(dir, "moves.txt", &report)
+}
+
+pub fn part1(input: &str) -> Result<u32, String> {
+    move_counts(input).map(|moves| moves.len() as u32)
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use std::path::PathBuf;
+
+    const EXAMPLE: &str = "\
+v...>>.vv>
+.vv>>.vv..
+>>.>v>...v
+>>v>>.>.v.
+v>v.vv.v..
+>.>>..v...
+.vv..>.>v.
+v.v..>>v.v
+....v..v.>";
+
+    fn stepped(map: &Map) -> Map {
+        let mut map = map.clone();
+        assert!(map.step().any());
+        map
+    }
+
+    #[test]
+    fn empty_map() {
+        assert_eq!(Err(String::from("The map has no cells")), part1(""));
+        assert_eq!(Err(String::from("The map has no cells")), part1("\n>>v\n"));
+    }
+
+    #[test]
+    fn invalid_map() {
+        assert_eq!(Err(String::from("Row 1 has length 2, expected 3")), part1(">.v\n.>\n"));
+        assert_eq!(Err(String::from("Row 1 has length 4, expected 3")), part1(">.v\n.>..\n"));
+        assert_eq!(Err(String::from("Unexpected cell '<' in row 0")), part1(">.<\n"));
+    }
 
     #[test]
     fn moves_one_by_one() {
-        let step0 = Map::parse("...>>>>>...");
-        let step1 = Map::parse("...>>>>.>..");
-        let step2 = Map::parse("...>>>.>.>.");
+        let step0 = Map::parse("...>>>>>...").unwrap();
+        let step1 = Map::parse("...>>>>.>..").unwrap();
+        let step2 = Map::parse("...>>>.>.>.").unwrap();
 
-        assert_eq!(step1.contents, step0.step_dir(&Direction::East).unwrap().contents);
-        assert_eq!(step2.contents, step1.step_dir(&Direction::East).unwrap().contents);
+        assert_eq!(step1, step0.step_dir(&Direction::East).unwrap());
+        assert_eq!(step2, step1.step_dir(&Direction::East).unwrap());
     }
 
     #[test]
     fn moves_over_edge() {
-        let step0 = Map::parse("...>");
-        let step1 = Map::parse(">...");
+        let step0 = Map::parse("...>").unwrap();
+        let step1 = Map::parse(">...").unwrap();
 
-        assert_eq!(step1.contents, step0.step_dir(&Direction::East).unwrap().contents);
+        assert_eq!(step1, step0.step_dir(&Direction::East).unwrap());
     }
 
     #[test]
@@ -129,11 +317,35 @@ mod test {
         let map = Map::parse("\
 ....
 >>>>
-....");
+....").unwrap();
         assert!(map.step_dir(&Direction::East).is_none());
         assert!(map.step_dir(&Direction::South).is_none());
     }
 
+    #[test]
+    fn wide_rows() {
+        // Rows over several words, where cucumbers move across word boundaries and wrap round.
+        let row = |cells: &[(usize, char)]| {
+            let mut row = vec!['.'; 130];
+            for &(x, c) in cells {
+                row[x] = c;
+            }
+            row.into_iter().collect::<String>()
+        };
+        let step0 = Map::parse(&[
+            row(&[(63, '>'), (65, 'v'), (127, '>'), (129, '>')]),
+            row(&[(0, 'v'), (129, '>')]),
+        ].join("\n")).unwrap();
+        let expected = [
+            row(&[(0, '>'), (64, '>'), (128, '>')]),
+            row(&[(0, 'v'), (65, 'v'), (129, '>')]),
+        ].join("\n");
+        let step1 = Map::parse(&expected).unwrap();
+
+        assert_eq!(step1, stepped(&step0));
+        assert_eq!(format!("{}\n", expected), format!("{:?}", step1));
+    }
+
     #[test]
     fn basic() {
         let step0 = Map::parse("\
@@ -141,35 +353,66 @@ mod test {
 .>v.
 .v>.
 ....
-");
+").unwrap();
         let step1 = Map::parse("\
 ....
 .>..
 ..v>
-.v..");
+.v..").unwrap();
         let step2 = Map::parse("\
 .v..
 ..>.
 >...
-..v.");
+..v.").unwrap();
+
+        assert_eq!(step1, stepped(&step0));
+        assert_eq!(step2, stepped(&step1));
+
+        assert_eq!(Moves { east: 1, south: 2 }, step0.clone().step());
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("advent2021-day25-{}-{}", name, std::process::id()))
+    }
+
+    fn read(dir: &Path, name: &str) -> String {
+        std::fs::read_to_string(dir.join(name)).unwrap()
+    }
+
+    #[test]
+    fn pgm_frames() {
+        let dir = temp_dir("pgm");
+        dump_steps("v.\n..\n>>", 1, FrameFormat::Pgm, &dir).unwrap();
+
+        assert_eq!("P2\n2 3\n2\n0 2\n2 2\n1 1\n", read(&dir, "step-000.pgm"));
+        assert_eq!("P2\n2 3\n2\n2 2\n0 2\n1 1\n", read(&dir, "step-001.pgm"));
+        assert_eq!(read(&dir, "step-001.pgm"), read(&dir, "step-002.pgm"));
+        assert_eq!("1 0 1\n2 0 0\n", read(&dir, "moves.txt"));
 
-        assert_eq!(step1.contents, step0.step().unwrap().contents);
-        assert_eq!(step2.contents, step1.step().unwrap().contents);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn text_frames() {
+        let moves = move_counts(EXAMPLE).unwrap();
+        assert_eq!(58, moves.len());
+        assert_eq!(Moves { east: 0, south: 0 }, moves[57]);
+        assert!(moves[..57].iter().all(Moves::any));
+
+        let dir = temp_dir("text");
+        dump_steps(EXAMPLE, 10, FrameFormat::Text, &dir).unwrap();
+
+        assert_eq!(format!("{}\n", EXAMPLE), read(&dir, "step-000.txt"));
+        assert!(dir.join("step-050.txt").exists() && dir.join("step-058.txt").exists());
+        assert!(!dir.join("step-051.txt").exists());
+        assert_eq!(Some("58 0 0"), read(&dir, "moves.txt").lines().last());
+
+        std::fs::remove_dir_all(&dir).unwrap();
     }
 
     #[test]
     fn given_example() {
-        let input = "\
-v...>>.vv>
-.vv>>.vv..
->>.>v>...v
->>v>>.>.v.
-v>v.vv.v..
->.>>..v...
-.vv..>.>v.
-v.v..>>v.v
-....v..v.>";
-        let step0 = Map::parse(input);
+        let step0 = Map::parse(EXAMPLE).unwrap();
         let step1 = Map::parse("\
 ....>.>v.>
 v.v>.>v.v.
@@ -179,9 +422,9 @@ v.v>.>v.v.
 v>>.>vvv..
 ..v...>>..
 vv...>>vv.
->.v.v..v.v");
+>.v.v..v.v").unwrap();
 
-        assert_eq!(step1.contents, step0.step().unwrap().contents);
-        assert_eq!(58, part1(input));
+        assert_eq!(step1, stepped(&step0));
+        assert_eq!(Ok(58), part1(EXAMPLE));
     }
-}
\ No newline at end of file
+}
@@ -25,6 +25,7 @@ pub mod day18;
 pub mod day19;
 
 mod vector;
+pub mod netpbm;
 mod day19inputs;
 pub mod day20;
 pub mod day21;
@@ -2,33 +2,9 @@ use std::env;
 use std::fs;
 use std::path::Path;
 use std::time::SystemTime;
-use advent2021::{day21, day24, day25};
-
-mod day1;
-mod challenge3;
-mod challenge4;
-mod challenge5;
-mod challenge6;
-mod day4;
-mod day5;
-mod day6;
-mod day7;
-mod day8;
-mod day9;
-mod day10;
-mod day11;
-mod day12;
-mod day13;
-mod day14;
-mod day15;
-mod day16;
-mod day17;
-mod day18;
-mod day19;
-mod vector;
-mod day20;
-mod day22;
-mod day23;
+use advent2021::{challenge3, challenge4, challenge5, challenge6};
+use advent2021::{day1, day4, day5, day6, day7, day8, day9, day10, day11, day12, day13, day14};
+use advent2021::{day15, day16, day17, day18, day19, day20, day21, day22, day23, day24, day25};
 
 fn main() {
     let args: Vec<String> = env::args().collect();
@@ -63,8 +39,43 @@ fn main() {
     }
 
     let contents = contents.unwrap();
+    let dump_dir = flag_value(&args, "--dump-dir").map(Path::new);
     let before = SystemTime::now();
 
+    if let Some(dir) = dump_dir {
+        let result = match challenge_no {
+            21 => day11::dump_steps(&contents, 100, dir),
+            22 => day11::dump_steps(&contents, day11::part2(&contents) as usize, dir),
+            25 | 26 => day13::dump_folds(&contents, dir),
+            49 => {
+                let every = match flag_value(&args, "--every").map(str::parse::<usize>) {
+                    None if !args.iter().any(|arg| arg == "--every") => 1,
+                    Some(Ok(every)) if every > 0 => every,
+                    _ => {
+                        println!("--every should be followed by a positive number of steps");
+                        return;
+                    }
+                };
+                let format = match flag_value(&args, "--format") {
+                    None if !args.iter().any(|arg| arg == "--format") => day25::FrameFormat::Pgm,
+                    Some("pgm") => day25::FrameFormat::Pgm,
+                    Some("text") => day25::FrameFormat::Text,
+                    _ => {
+                        println!("--format should be followed by text or pgm");
+                        return;
+                    }
+                };
+                day25::dump_steps(&contents, every, format, dir)
+            }
+            _ => Ok(()),
+        };
+
+        if let Err(err) = result {
+            println!("Could not write images: {}", err);
+            return;
+        }
+    }
+
     // TODO: Standardize some API for all the challenges.
     match challenge_no {
         1 => { println!("{:?}", day1::part1(&contents)) }
@@ -101,22 +112,166 @@ fn main() {
         32 => { println!("{}", day16::part2(&contents)) }
         33 => { println!("{}", day17::part1(&contents)) }
         34 => { println!("{}", day17::part2(&contents)) }
-        35 => { println!("{}", day18::part1(&contents)) }
-        36 => { println!("{}", day18::part2(&contents)) }
+        35 => match &args[..] {
+            [_, _, flag, a, b, ..] if flag == "--trace" => {
+                day18::trace_addition(a, b).iter().for_each(|step| println!("{}", step))
+            }
+            _ => println!("{}", day18::part1(&contents)),
+        }
+        36 => {
+            if args.iter().any(|arg| arg == "--pair") {
+                println!("{:?}", day18::best_pair(&contents))
+            } else {
+                println!("{}", day18::part2(&contents))
+            }
+        }
         37 => { println!("{}", day19::part1(&contents)) }
         38 => { println!("{}", day19::part2(&contents)) }
-        39 => { println!("{}", day20::part1(&contents)) }
-        40 => { println!("{}", day20::part2(&contents)) }
-        41 => { println!("{}", day21::part1(6, 9)) }
-        42 => { println!("{:?}", day21::part2(6, 9)) }
+        39 | 40 => {
+            let image = match flag_value(&args, "--image").map(fs::read_to_string).transpose() {
+                Ok(image) => image,
+                Err(err) => { println!("{:?}", err); return; }
+            };
+
+            let iterations = if challenge_no == 39 { 2 } else { 50 };
+            match day20::enhance_with(&contents, image.as_deref(), iterations, dump_dir) {
+                Ok(count) => println!("{}", count),
+                Err(err) => println!("{}", err),
+            }
+        }
+        41 => { println!("{:?}", day21::parse(&contents).and_then(|game| day21::part1(&game))) }
+        42 => {
+            if args.iter().any(|arg| arg == "--optimal-play") {
+                let rules = day21::GameRules::QUANTUM;
+                let optimal =
+                    day21::parse(&contents).and_then(|g| day21::solve_choice_variant(&g, &rules));
+
+                match (optimal, flag_value(&args, "--policy")) {
+                    (Ok(optimal), Some(path)) => {
+                        if let Err(err) = fs::write(path, optimal.policy_table()) {
+                            println!("Could not write policy: {}", err);
+                        }
+                        println!("{:?}", optimal.win_probabilities)
+                    }
+                    (Ok(optimal), None) => println!("{:?}", optimal.win_probabilities),
+                    (Err(err), _) => println!("{}", err),
+                }
+            } else if args.iter().any(|arg| arg == "--report") {
+                let rules = day21::GameRules::QUANTUM;
+                let report = day21::parse(&contents)
+                    .and_then(|game| day21::quantum_report(&game, &rules));
+                match report {
+                    Ok(report) => println!("{}", report),
+                    Err(err) => println!("{}", err),
+                }
+            } else {
+                println!("{:?}", day21::parse(&contents).and_then(|game| day21::part2(&game)))
+            }
+        }
+        43 | 44 if args.iter().any(|arg| arg == "--export" || arg == "--slice") => {
+            export_reactor(&contents, &args)
+        }
         43 => { println!("{}", day22::part1(&contents)) }
         44 => { println!("{}", day22::part2(&contents)) }
-        45 => { day23::part1()}
-        46 => { day23::part2()}
-        47 => { day24::part1(&contents) }
-        49 => { println!("{}", day25::part1(&contents)) }
+        45 | 46 if args.iter().any(|arg| arg == "--stats") => {
+            let input = if challenge_no == 45 {
+                contents.clone()
+            } else {
+                day23::unfold(&contents)
+            };
+            for strategy in [day23::Strategy::Dijkstra, day23::Strategy::AStar] {
+                match day23::solve_with(&input, strategy) {
+                    Ok((solution, stats)) => {
+                        println!("{:?}: {} {:?}", strategy, solution.energy, stats)
+                    }
+                    Err(err) => println!("{}", err),
+                }
+            }
+        }
+        45 | 46 if args.iter().any(|arg| arg == "--moves") => {
+            let solution = if challenge_no == 45 {
+                day23::solve(&contents)
+            } else {
+                day23::solve_unfolded(&contents)
+            };
+            match solution {
+                Ok(solution) => println!("{}", solution.render()),
+                Err(err) => println!("{}", err),
+            }
+        }
+        45 => { println!("{:?}", day23::part1(&contents)) }
+        46 => { println!("{:?}", day23::part2(&contents)) }
+        47 | 48 if args.iter().any(|arg| arg == "--symbolic") => {
+            match day24::symbolic_trace(&contents) {
+                Ok(trace) => {
+                    for (line, value) in trace.steps {
+                        println!("{} {}", line, value.to_short_string(200));
+                    }
+                    for (name, value) in "wxyz".chars().zip(trace.registers) {
+                        println!("{} = {}", name, value.to_short_string(200));
+                    }
+                }
+                Err(err) => println!("{}", err),
+            }
+        }
+        47 | 48 if flag_value(&args, "--trace").is_some() => {
+            let input = flag_value(&args, "--trace").unwrap();
+            let breakpoints = args.iter().any(|arg| arg == "--break");
+            let stdin = std::io::stdin();
+            let stdout = std::io::stdout();
+            let result = day24::debug(&contents, input, breakpoints, stdin.lock(), stdout);
+            if let Err(err) = result {
+                println!("{}", err);
+            }
+        }
+        47 | 48 if args.iter().any(|arg| arg == "--search") => {
+            println!("{:?}", day24::search_model_numbers(&contents))
+        }
+        47 => { println!("{:?}", day24::part1(&contents)) }
+        48 => { println!("{:?}", day24::part2(&contents)) }
+        49 if args.iter().any(|arg| arg == "--moves") => {
+            match day25::move_counts(&contents) {
+                Ok(moves) => for (step, moves) in moves.iter().enumerate() {
+                    println!("step {:>4}: {:>5} east {:>5} south",
+                             step + 1, moves.east, moves.south);
+                },
+                Err(err) => println!("{}", err),
+            }
+        }
+        49 => { println!("{:?}", day25::part1(&contents)) }
         _ => { println!("Unknown challenge no."); }
     }
 
     println!("{:?}", SystemTime::now().duration_since(before).unwrap())
 }
+
+/// Writes the final reactor state to the file given by `--export`, and draws the slice at the z
+/// given by `--slice`.
+fn export_reactor(contents: &str, args: &[String]) {
+    let reactor = match day22::parse::<3>(contents) {
+        Ok(steps) => day22::Reactor::from_steps(&steps),
+        Err(err) => { println!("{}", err); return; }
+    };
+
+    if let Some(path) = flag_value(args, "--export") {
+        if let Err(err) = fs::write(path, reactor.to_steps(&["x", "y", "z"])) {
+            println!("Could not write {}: {}", path, err);
+        }
+    }
+
+    if let Some(z) = flag_value(args, "--slice") {
+        match z.parse().map_err(|_| format!("Could not parse z: {}", z))
+            .and_then(|z| reactor.render_slice(z)) {
+            Ok(slice) => print!("{}", slice),
+            Err(err) => println!("{}", err),
+        }
+    }
+}
+
+/// Returns the argument following `flag`, if `flag` was passed.
+fn flag_value<'a>(args: &'a [String], flag: &str) -> Option<&'a str> {
+    args.iter()
+        .position(|arg| arg == flag)
+        .and_then(|i| args.get(i + 1))
+        .map(String::as_str)
+}
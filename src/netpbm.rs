@@ -0,0 +1,157 @@
+//! Reading and writing the plain (ASCII) variants of the Netpbm formats: PBM (P1) for bitmaps and
+//! PGM (P2) for greyscale images. See http://netpbm.sourceforge.net/doc/pbm.html.
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+// The format asks that no line be longer than this.
+const MAX_LINE_LENGTH: usize = 70;
+
+/// A bitmap read back from a PBM file, stored row by row. `true` is black (a `1` in the file).
+#[derive(Debug, PartialEq, Eq)]
+pub struct Bitmap {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<bool>,
+}
+
+impl Bitmap {
+    pub fn get(&self, x: usize, y: usize) -> bool {
+        self.pixels[y * self.width + x]
+    }
+}
+
+/// Appends `values` to `output`, separated by `separator` and wrapped to the maximum line length.
+fn push_wrapped<I: Iterator<Item = String>>(output: &mut String, values: I, separator: &str) {
+    let mut line_length = 0;
+
+    for value in values {
+        if line_length > 0 && line_length + separator.len() + value.len() > MAX_LINE_LENGTH {
+            output.push('\n');
+            line_length = 0;
+        } else if line_length > 0 {
+            output.push_str(separator);
+            line_length += separator.len();
+        }
+
+        line_length += value.len();
+        output.push_str(&value);
+    }
+
+    output.push('\n');
+}
+
+pub fn to_pbm<F: Fn(usize, usize) -> bool>(width: usize, height: usize, pixel: F) -> String {
+    let mut output = format!("P1\n{} {}\n", width, height);
+
+    for y in 0..height {
+        let row = (0..width).map(|x| String::from(if pixel(x, y) { "1" } else { "0" }));
+        push_wrapped(&mut output, row, "");
+    }
+
+    output
+}
+
+/// Writes a greyscale image. Following the format, 0 is black and `max_value` is white.
+pub fn to_pgm<F: Fn(usize, usize) -> u16>(width: usize, height: usize, max_value: u16, pixel: F)
+    -> String {
+    let mut output = format!("P2\n{} {}\n{}\n", width, height, max_value);
+
+    for y in 0..height {
+        let row = (0..width).map(|x| pixel(x, y).min(max_value).to_string());
+        push_wrapped(&mut output, row, " ");
+    }
+
+    output
+}
+
+pub fn from_pbm(input: &str) -> Result<Bitmap, String> {
+    // Comments run from a '#' to the end of the line.
+    let contents: String = input.lines()
+        .map(|line| line.split('#').next().unwrap())
+        .collect::<Vec<&str>>()
+        .join("\n");
+
+    let mut tokens = contents.split_whitespace();
+
+    if tokens.next() != Some("P1") {
+        return Err(String::from("Not a plain PBM file (expected \"P1\")"));
+    }
+
+    let mut dimension = |name: &str| -> Result<usize, String> {
+        tokens.next()
+            .ok_or(format!("Missing {}", name))?
+            .parse()
+            .map_err(|_| format!("Could not parse {}", name))
+    };
+    let width = dimension("width")?;
+    let height = dimension("height")?;
+
+    // In the plain format the pixels don't need to be separated by whitespace.
+    let pixels: Vec<bool> = tokens
+        .flat_map(|token| token.chars())
+        .map(|c| match c {
+            '0' => Ok(false),
+            '1' => Ok(true),
+            other => Err(format!("Unexpected pixel '{}'", other)),
+        })
+        .collect::<Result<_, _>>()?;
+
+    if pixels.len() != width * height {
+        return Err(format!("Expected {} pixels, found {}", width * height, pixels.len()));
+    }
+
+    Ok(Bitmap { width, height, pixels })
+}
+
+/// Writes `contents` to `name` in `dir`, creating the directory if needed.
+pub fn write_to_dir(dir: &Path, name: &str, contents: &str) -> io::Result<()> {
+    fs::create_dir_all(dir)?;
+    fs::write(dir.join(name), contents)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pbm_round_trip() {
+        let pbm = to_pbm(3, 2, |x, y| (x + y) % 2 == 0);
+        assert_eq!("P1\n3 2\n101\n010\n", pbm);
+
+        let bitmap = from_pbm(&pbm).unwrap();
+        let pixels = vec![true, false, true, false, true, false];
+        assert_eq!(Bitmap { width: 3, height: 2, pixels }, bitmap);
+        assert_eq!(true, bitmap.get(2, 0));
+    }
+
+    #[test]
+    fn pbm_with_comments_and_spacing() {
+        let bitmap = from_pbm("P1\n# A comment\n2 2 # Another\n1 0\n0\n1").unwrap();
+        assert_eq!(vec![true, false, false, true], bitmap.pixels);
+    }
+
+    #[test]
+    fn pbm_errors() {
+        assert!(from_pbm("P2\n1 1\n1").is_err());
+        assert!(from_pbm("P1\n2 2\n101").is_err());
+        assert!(from_pbm("P1\n1 1\n2").is_err());
+        assert!(from_pbm("P1\nx 1\n1").is_err());
+    }
+
+    #[test]
+    fn long_rows_are_wrapped() {
+        let pbm = to_pbm(100, 1, |_, _| true);
+        assert!(pbm.lines().all(|line| line.len() <= MAX_LINE_LENGTH));
+        assert_eq!(100, from_pbm(&pbm).unwrap().pixels.len());
+
+        let pgm = to_pgm(40, 1, 9, |x, _| x as u16);
+        assert!(pgm.lines().all(|line| line.len() <= MAX_LINE_LENGTH));
+    }
+
+    #[test]
+    fn pgm() {
+        assert_eq!("P2\n2 2\n9\n0 9\n9 5\n", to_pgm(2, 2, 9, |x, y| [[0, 12], [9, 5]][y][x]));
+    }
+}